@@ -0,0 +1,123 @@
+// Compares the first kernel of the multi work group compaction and raycasting
+// with their conflict free variants, e.g. with
+//     cargo bench --bench compaction --no-default-features --features egl
+// See benches/scan.rs about the GPU times of software drivers.
+use compute_shader::{Buffer, Context, Param, Profiler, Program, ShaderTemplate};
+use gl::types::*;
+use std::collections::HashMap;
+
+const WARMUP: usize = 3;
+const SAMPLES: usize = 20;
+
+fn program(name: &str, source: &str, substs: &HashMap<&str, Param>) -> Program {
+    let template = ShaderTemplate::parse(name, source).unwrap();
+    let kernel = template.compile(substs, gl::COMPUTE_SHADER).unwrap();
+    Program::new(vec![(kernel, gl::COMPUTE_SHADER)]).unwrap()
+}
+
+fn bench(profiler: &mut Profiler, name: &str, program: &Program, work_groups: usize) {
+    for _ in 0..WARMUP {
+        program.dispatch(work_groups as GLuint, 1, 1);
+    }
+    unsafe { gl::Finish() };
+
+    for _ in 0..SAMPLES {
+        profiler.scope(name, || {
+            program.dispatch(work_groups as GLuint, 1, 1);
+            // So that the CPU time includes the GPU work
+            unsafe { gl::Finish() };
+        });
+    }
+}
+
+fn main() {
+    let _context = Context::new().unwrap();
+    let mut profiler = Profiler::new();
+
+    // Keeps the even elements of N elements, see multi_wg_compaction1
+    const N: usize = 1 << 22;
+    for &b in &[128, 1024] {
+        let mut substs: HashMap<&str, Param> = HashMap::new();
+        substs.insert("N", N.into());
+        substs.insert("B", b.into());
+        let input = Buffer::from_slice(&(0..N as GLuint).collect::<Vec<_>>());
+        // sums, offsets, results and data
+        let output = Buffer::<GLuint>::new(N / b + 3 * N);
+
+        for (name, source) in [
+            (
+                "multi_wg_compaction1",
+                include_str!("../shaders/multi_wg_compaction/multi_wg_compaction1.comp.glsl"),
+            ),
+            (
+                "multi_wg_compaction1_conflict_free",
+                include_str!(
+                    "../shaders/multi_wg_compaction/multi_wg_compaction1_conflict_free.comp.glsl"
+                ),
+            ),
+        ] {
+            let program = program(name, source, &substs);
+            input.bind(0);
+            output.bind(1);
+            bench(&mut profiler, &format!("{} B={}", name, b), &program, N / b);
+        }
+    }
+
+    // Casts RAYS rays through a chunk with a full floor, see
+    // multi_wg_raycasting1
+    const CHUNK: usize = 7;
+    const CHUNK_SIZE: usize = CHUNK * CHUNK * CHUNK;
+    const RAYS: usize = 1 << 20;
+    for &b in &[128, 1024] {
+        let mut substs: HashMap<&str, Param> = HashMap::new();
+        for name in ["CHUNK_X", "CHUNK_Y", "CHUNK_Z"] {
+            substs.insert(name, CHUNK.into());
+        }
+        substs.insert("CHUNK_SIZE", CHUNK_SIZE.into());
+        substs.insert("N", RAYS.into());
+        substs.insert("B", b.into());
+        // The chunk, indexed by z, y and x, then the unused ray_start uvec4
+        let mut chunk = vec![0; CHUNK_SIZE.next_multiple_of(4) + 4];
+        for z in 0..CHUNK {
+            for x in 0..CHUNK {
+                chunk[CHUNK * CHUNK * z + CHUNK * (CHUNK - 1) + x] = 1;
+            }
+        }
+        let input = Buffer::<GLuint>::from_slice(&chunk);
+        // sums, offsets and has_hit, then the uvec4 hits and compact_hits
+        let output = Buffer::<GLuint>::new((RAYS / b + 2 * RAYS).next_multiple_of(4) + 8 * RAYS);
+        // CompactCounter
+        let counter = Buffer::<GLuint>::new(8);
+
+        for (name, source) in [
+            (
+                "multi_wg_raycasting1",
+                include_str!("../shaders/multi_wg_raycasting/multi_wg_raycasting1.comp.glsl"),
+            ),
+            (
+                "multi_wg_raycasting1_conflict_free",
+                include_str!(
+                    "../shaders/multi_wg_raycasting/multi_wg_raycasting1_conflict_free.comp.glsl"
+                ),
+            ),
+        ] {
+            let program = program(name, source, &substs);
+            input.bind(0);
+            output.bind(1);
+            counter.bind(2);
+            bench(
+                &mut profiler,
+                &format!("{} B={}", name, b),
+                &program,
+                RAYS / b,
+            );
+        }
+    }
+    profiler.wait();
+
+    println!(
+        "compaction of {} uints and raycasting of {} rays, first kernel only",
+        N, RAYS
+    );
+    println!("{}", profiler.report());
+}
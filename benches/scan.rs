@@ -0,0 +1,44 @@
+// Compares the block scan algorithms, e.g. with
+//     cargo bench --no-default-features --features egl
+// GPU times need a driver with real timer queries, software ones like llvmpipe
+// report about zero and only the CPU times (which wait for the GPU) are useful.
+use compute_shader::{Buffer, Context, Profiler, Scan, ScanAlgorithm, ScanKind, ScanOp};
+
+const LEN: usize = 1 << 22;
+const WARMUP: usize = 3;
+const SAMPLES: usize = 20;
+
+fn main() {
+    let _context = Context::new().unwrap();
+    let input = Buffer::from_slice(&vec![1.0f32; LEN]);
+
+    let mut profiler = Profiler::new();
+    for &block_len in &[256, 1024, 2048] {
+        for algorithm in [
+            ScanAlgorithm::Blelloch,
+            ScanAlgorithm::ConflictFree,
+            ScanAlgorithm::DecoupledLookBack,
+        ] {
+            let scan = Scan::<f32>::with_algorithm(ScanOp::Add, algorithm, block_len).unwrap();
+            // After a fallback, the algorithm actually used
+            let name = format!("{:?} {}", scan.algorithm(), block_len);
+
+            for _ in 0..WARMUP {
+                scan.run(&input, ScanKind::Exclusive);
+            }
+            unsafe { gl::Finish() };
+
+            for _ in 0..SAMPLES {
+                profiler.scope(&name, || {
+                    scan.run(&input, ScanKind::Exclusive);
+                    // So that the CPU time includes the GPU work
+                    unsafe { gl::Finish() };
+                });
+            }
+        }
+    }
+    profiler.wait();
+
+    println!("exclusive scan of {} floats", LEN);
+    println!("{}", profiler.report());
+}
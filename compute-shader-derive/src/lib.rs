@@ -0,0 +1,118 @@
+use proc_macro::TokenStream;
+use proc_macro2::Span;
+use quote::quote;
+use syn::{parse_macro_input, Data, DeriveInput, Fields, Ident};
+
+/// Derives `compute_shader::Std430` for a struct with named fields, laying the
+/// fields out in declaration order with the std430 alignment rules, exactly
+/// like the members of a GLSL `layout(std430) buffer` block.
+#[proc_macro_derive(Std430)]
+pub fn derive_std430(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+
+    let fields = match &input.data {
+        Data::Struct(data) => {
+            match &data.fields {
+                Fields::Named(fields) if !fields.named.is_empty() => &fields.named,
+                _ => return syn::Error::new_spanned(
+                    &input.ident,
+                    "Std430 can only be derived for structs with named fields, like GLSL structs",
+                )
+                .to_compile_error()
+                .into(),
+            }
+        }
+        _ => {
+            return syn::Error::new_spanned(&input.ident, "Std430 can only be derived for structs")
+                .to_compile_error()
+                .into()
+        }
+    };
+
+    let name = &input.ident;
+    let krate = quote!(::compute_shader);
+
+    let idents: Vec<&Ident> = fields.iter().map(|f| f.ident.as_ref().unwrap()).collect();
+    let names: Vec<String> = idents.iter().map(|i| i.to_string()).collect();
+    let types: Vec<&syn::Type> = fields.iter().map(|f| &f.ty).collect();
+
+    // Every field must be Std430 itself, which for generic structs depends on
+    // the type parameters
+    let mut generics = input.generics.clone();
+    let where_clause = generics.make_where_clause();
+    for ty in &types {
+        where_clause
+            .predicates
+            .push(syn::parse_quote!(#ty: #krate::Std430));
+    }
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    let offsets: Vec<Ident> = (0..idents.len())
+        .map(|i| Ident::new(&format!("offset{}", i), Span::call_site()))
+        .collect();
+    let indices: Vec<usize> = (0..idents.len()).collect();
+
+    // Each field starts at the end of the previous one, rounded up to its own
+    // alignment
+    let mut offset_stmts = Vec::new();
+    for (i, (offset, ty)) in offsets.iter().zip(&types).enumerate() {
+        let end_of_previous = if i == 0 {
+            quote!(0)
+        } else {
+            let previous_offset = &offsets[i - 1];
+            let previous_ty = types[i - 1];
+            quote!(#previous_offset + <#previous_ty as #krate::Std430>::SIZE)
+        };
+        offset_stmts.push(quote! {
+            let #offset = #krate::std430::align_to(
+                #end_of_previous,
+                <#ty as #krate::Std430>::ALIGN,
+            );
+        });
+    }
+    let last = idents.len() - 1;
+    let last_ty = types[last];
+
+    let expanded = quote! {
+        impl #impl_generics #krate::Std430 for #name #ty_generics #where_clause {
+            const ALIGN: usize = {
+                let align = 1;
+                #(let align = #krate::std430::max(align, <#types as #krate::Std430>::ALIGN);)*
+                align
+            };
+            const SIZE: usize = #krate::std430::align_to(
+                Self::FIELDS[#last].1 + <#last_ty as #krate::Std430>::SIZE,
+                Self::ALIGN,
+            );
+            const FIELDS: &'static [(&'static str, usize)] = &{
+                #(#offset_stmts)*
+                [#((#names, #offsets)),*]
+            };
+            const LAYOUT: #krate::std430::Layout = #krate::std430::Layout::Struct(&[
+                #((#names, Self::FIELDS[#indices].1, &<#types as #krate::Std430>::LAYOUT)),*
+            ]);
+
+            fn write_std430(&self, bytes: &mut [u8]) {
+                #(
+                    let offset = Self::FIELDS[#indices].1;
+                    #krate::Std430::write_std430(
+                        &self.#idents,
+                        &mut bytes[offset..offset + <#types as #krate::Std430>::SIZE],
+                    );
+                )*
+            }
+
+            fn read_std430(bytes: &[u8]) -> Self {
+                #name {
+                    #(
+                        #idents: <#types as #krate::Std430>::read_std430(
+                            &bytes[Self::FIELDS[#indices].1..
+                                Self::FIELDS[#indices].1 + <#types as #krate::Std430>::SIZE],
+                        ),
+                    )*
+                }
+            }
+        }
+    };
+
+    expanded.into()
+}
@@ -0,0 +1,18 @@
+// Fills in the indirect dispatch parameters of CompactCounter, once every work
+// group of compaction_flags added its count
+#version 450 core
+
+layout(local_size_x = 1, local_size_y = 1, local_size_z = 1) in;
+
+layout(std430, binding = 2) buffer Counter {
+  uint len;
+  uvec3 num_groups;
+}
+counter;
+
+// The work group size of the kernel the counter is meant for
+layout(location = 0) uniform uint group_size;
+
+void main() {
+  counter.num_groups = uvec3((counter.len + group_size - 1) / group_size, 1, 1);
+}
@@ -0,0 +1,62 @@
+// The first step of stream compaction: flags the elements to keep, which are
+// then scanned to find where they go, see compaction_scatter. Also counts them
+#version 450 core
+// For TYPE int64_t and uint64_t, a warning where unsupported
+#extension GL_ARB_gpu_shader_int64 : enable
+
+#define THREADS 256
+// The type of the elements
+#define TYPE float
+// Which elements to keep, a boolean expression of the element `x` and its index
+// `i`. It can also read the other elements from input_data, see Runs
+#define PREDICATE
+// Function definitions PREDICATE can call, usually empty
+#define FUNCTIONS
+
+layout(local_size_x = THREADS, local_size_y = 1, local_size_z = 1) in;
+
+layout(std430, binding = 0) readonly buffer InputData { TYPE data[]; }
+input_data;
+
+layout(std430, binding = 1) writeonly buffer Flags { uint flags[]; }
+keep;
+
+// CompactCounter, zeroed before the dispatch. Only `len` is written here, see
+// compaction_dispatch
+layout(std430, binding = 2) buffer Counter {
+  uint len;
+  uvec3 num_groups;
+}
+counter;
+
+layout(location = 0) uniform uint len;
+
+// The number of elements this work group keeps, so that there is one global
+// atomic per work group instead of one per element
+shared uint kept;
+
+FUNCTIONS
+
+bool predicate(TYPE x, uint i) { return PREDICATE; }
+
+void main() {
+  uint i = gl_GlobalInvocationID.x;
+
+  if (gl_LocalInvocationIndex == 0) {
+    kept = 0;
+  }
+  barrier();
+
+  if (i < len) {
+    bool flag = predicate(input_data.data[i], i);
+    keep.flags[i] = uint(flag);
+    if (flag) {
+      atomicAdd(kept, 1);
+    }
+  }
+  barrier();
+
+  if (gl_LocalInvocationIndex == 0 && kept != 0) {
+    atomicAdd(counter.len, kept);
+  }
+}
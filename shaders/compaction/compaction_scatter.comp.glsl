@@ -0,0 +1,51 @@
+// The last step of stream compaction: moves every flagged element to its
+// offset, the exclusive scan of the flags. The order is preserved. When
+// partitioning, the other elements are moved after all the flagged ones
+#version 450 core
+// For TYPE int64_t and uint64_t, a warning where unsupported
+#extension GL_ARB_gpu_shader_int64 : enable
+
+#define THREADS 256
+// The type of the elements
+#define TYPE float
+
+layout(local_size_x = THREADS, local_size_y = 1, local_size_z = 1) in;
+
+layout(std430, binding = 0) readonly buffer InputData { TYPE data[]; }
+input_data;
+
+layout(std430, binding = 1) readonly buffer Flags { uint flags[]; }
+keep;
+
+layout(std430, binding = 2) readonly buffer Offsets { uint offsets[]; }
+offsets;
+
+layout(std430, binding = 3) writeonly buffer OutputData { TYPE data[]; }
+output_data;
+
+// CompactCounter, with the number of flagged elements
+layout(std430, binding = 4) readonly buffer Counter {
+  uint len;
+  uvec3 num_groups;
+}
+counter;
+
+layout(location = 0) uniform uint len;
+// Whether to also move the elements that are not flagged
+layout(location = 1) uniform bool keep_rest;
+
+// The offset of element `i`. The offsets of the flagged elements are the
+// exclusive scan of the flags, and the others come after them in the same
+// order, since `i - offsets[i]` elements before `i` are not flagged
+uint offset(uint i) {
+  uint kept_before = offsets.offsets[i];
+  return keep.flags[i] != 0 ? kept_before : counter.len + (i - kept_before);
+}
+
+void main() {
+  uint i = gl_GlobalInvocationID.x;
+
+  if (i < len && (keep_rest || keep.flags[i] != 0)) {
+    output_data.data[offset(i)] = input_data.data[i];
+  }
+}
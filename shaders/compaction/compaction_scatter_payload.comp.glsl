@@ -0,0 +1,50 @@
+// Moves the payload of every flagged element to its offset, like
+// compaction_scatter does with the keys. The payload can be any std430 type, so
+// it is copied as 32 bit words
+#version 450 core
+
+#define THREADS 256
+
+layout(local_size_x = THREADS, local_size_y = 1, local_size_z = 1) in;
+
+layout(std430, binding = 1) readonly buffer Flags { uint flags[]; }
+keep;
+
+layout(std430, binding = 2) readonly buffer Offsets { uint offsets[]; }
+offsets;
+
+layout(std430, binding = 3) readonly buffer Counter {
+  uint len;
+  uvec3 num_groups;
+}
+counter;
+
+layout(std430, binding = 4) readonly buffer InputPayload { uint words[]; }
+input_payload;
+
+layout(std430, binding = 5) writeonly buffer OutputPayload { uint words[]; }
+output_payload;
+
+layout(location = 0) uniform uint len;
+// The array stride of the payload, in words
+layout(location = 1) uniform uint payload_words;
+// Whether to also move the payload of the elements that are not flagged, see
+// compaction_scatter
+layout(location = 2) uniform bool keep_rest;
+
+uint offset(uint i) {
+  uint kept_before = offsets.offsets[i];
+  return keep.flags[i] != 0 ? kept_before : counter.len + (i - kept_before);
+}
+
+void main() {
+  uint i = gl_GlobalInvocationID.x;
+
+  if (i < len && (keep_rest || keep.flags[i] != 0)) {
+    uint from = i * payload_words;
+    uint to = offset(i) * payload_words;
+    for (uint word = 0; word < payload_words; word++) {
+      output_payload.words[to + word] = input_payload.words[from + word];
+    }
+  }
+}
@@ -0,0 +1,75 @@
+// Counts the elements falling in each bin. Every work group counts its elements
+// in a private histogram in shared memory, so that most atomics stay in the
+// work group, and then adds it to the global histogram
+#version 450 core
+// For TYPE int64_t and uint64_t, a warning where unsupported
+#extension GL_ARB_gpu_shader_int64 : enable
+
+#define THREADS 256
+// The type of the elements
+#define TYPE float
+// The number of bins
+#define BINS 256
+// The bin of an element, an expression of `x` which can use the uniforms
+// range_min and range_scale. Bins past the last one are not counted
+#define BIN uint(x)
+// Histograms with more bins than this are counted in global memory directly
+#define MAX_PRIVATE_BINS 4096
+
+const bool PRIVATE = BINS <= MAX_PRIVATE_BINS;
+
+layout(local_size_x = THREADS, local_size_y = 1, local_size_z = 1) in;
+
+layout(std430, binding = 0) readonly buffer InputData { TYPE data[]; }
+input_data;
+
+// Zeroed before the dispatch
+layout(std430, binding = 1) buffer Histogram { uint counts[]; }
+histogram;
+
+layout(location = 0) uniform uint len;
+// The start of the first bin and the inverse of the width of the bins, for
+// BinFn::Uniform
+layout(location = 1) uniform float range_min;
+layout(location = 2) uniform float range_scale;
+
+shared uint private_histogram[PRIVATE ? BINS : 1];
+
+uint bin_of(TYPE x) { return BIN; }
+
+void count(uint bin) {
+  if (bin < BINS) {
+    if (PRIVATE) {
+      atomicAdd(private_histogram[bin], 1);
+    } else {
+      atomicAdd(histogram.counts[bin], 1);
+    }
+  }
+}
+
+void main() {
+  uint T = gl_LocalInvocationIndex;
+
+  if (PRIVATE) {
+    for (uint bin = T; bin < BINS; bin += THREADS) {
+      private_histogram[bin] = 0;
+    }
+  }
+  barrier();
+
+  // Each work group covers many elements, so that the private histogram is
+  // cleared and merged as few times as possible
+  uint stride = gl_NumWorkGroups.x * THREADS;
+  for (uint i = gl_GlobalInvocationID.x; i < len; i += stride) {
+    count(bin_of(input_data.data[i]));
+  }
+  barrier();
+
+  if (PRIVATE) {
+    for (uint bin = T; bin < BINS; bin += THREADS) {
+      if (private_histogram[bin] != 0) {
+        atomicAdd(histogram.counts[bin], private_histogram[bin]);
+      }
+    }
+  }
+}
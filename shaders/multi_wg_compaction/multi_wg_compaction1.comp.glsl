@@ -4,9 +4,11 @@
 
 // A single thread operates on two items at a time
 
-#define N -1337
-#define B -1337
+#define N
+#define B
 #define N_OVER_B (N / B)
+// Which elements to keep, a boolean expression of the element `x`
+#define PREDICATE x % 2 == 0
 
 layout(local_size_x = B / 2, local_size_y = 1, local_size_z = 1) in;
 
@@ -21,7 +23,10 @@ layout(std430, binding = 1) coherent buffer OutputData {
 }
 output_data;
 
-uint prefix_sum_return_total(inout uint offsets[B], uint T) {
+shared bool results[B];
+shared uint offsets[B];
+
+uint prefix_sum_return_total(uint T) {
   uint sum = -100;
   // **************************************************************************
   // Reduce
@@ -111,10 +116,7 @@ void prefix_sum(inout uint sums[N_OVER_B], uint T) {
   }
 }
 
-shared bool results[B];
-shared uint offsets[B];
-
-bool predicate(uint x) { return x % 2 == 0; }
+bool predicate(uint x) { return PREDICATE; }
 
 void main() {
   uint W = gl_WorkGroupID.x;
@@ -132,7 +134,7 @@ void main() {
 
   // Perform the prefix sum on the input data, returning the total sum
   // replacing the last element with 0
-  uint sum = prefix_sum_return_total(offsets, T);
+  uint sum = prefix_sum_return_total(T);
   if (T == 0) {
     output_data.sums[W] = sum;
   }
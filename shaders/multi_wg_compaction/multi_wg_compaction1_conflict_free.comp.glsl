@@ -0,0 +1,114 @@
+// Blelloch parallel prefix sum/scan, avoiding shared memory bank conflicts
+// https://developer.nvidia.com/gpugems/gpugems3/part-vi-gpu-computing/chapter-39-parallel-prefix-sum-scan-cuda
+// 39.2.3 Avoiding Bank Conflicts
+//
+// A drop-in replacement for multi_wg_compaction1, with the same parameters and
+// bindings. The flags stay in registers and only the offsets are scanned in
+// shared memory
+#version 450 core
+
+// A single thread operates on two items at a time
+
+// See multi_wg_compaction1
+#define N
+#define B
+#define N_OVER_B (N / B)
+#define PREDICATE x % 2 == 0
+// See multi_wg_prefix_sum1_conflict_free
+#define LOG_NUM_BANKS 5
+
+layout(local_size_x = B / 2, local_size_y = 1, local_size_z = 1) in;
+
+layout(std430, binding = 0) readonly buffer InputData { uint data[N]; }
+input_data;
+
+layout(std430, binding = 1) writeonly buffer OutputData {
+  uint sums[N_OVER_B];
+  uint offsets[N];
+  uint results[N];
+  uint data[N];
+}
+output_data;
+
+// One padding element every 1 << LOG_NUM_BANKS elements
+shared uint offsets[B + (B >> LOG_NUM_BANKS)];
+
+uint padded(uint n) { return n + (n >> LOG_NUM_BANKS); }
+
+// Only the shared memory is accessed between the steps, so barrier() is enough,
+// see multi_wg_prefix_sum1_conflict_free
+uint prefix_sum_return_total(uint T) {
+  // **************************************************************************
+  // Reduce
+  uint offset = 1;
+
+  for (uint d = B >> 1; d > 0; d >>= 1) {
+    barrier();
+
+    if (T < d) {
+      uint ai = padded(offset * (2 * T + 1) - 1);
+      uint bi = padded(offset * (2 * T + 2) - 1);
+      offsets[bi] += offsets[ai];
+    }
+
+    offset *= 2;
+  }
+
+  // **************************************************************************
+  // Down-sweep
+  barrier();
+  uint sum = offsets[padded(B - 1)];
+  barrier();
+  if (T == 0) {
+    offsets[padded(B - 1)] = 0;
+  }
+
+  for (uint d = 1; d < B; d *= 2) {
+    offset >>= 1;
+
+    barrier();
+
+    if (T < d) {
+      uint ai = padded(offset * (2 * T + 1) - 1);
+      uint bi = padded(offset * (2 * T + 2) - 1);
+
+      uint t = offsets[ai];
+
+      offsets[ai] = offsets[bi];
+      offsets[bi] += t;
+    }
+  }
+
+  barrier();
+  return sum;
+}
+
+bool predicate(uint x) { return PREDICATE; }
+
+void main() {
+  uint W = gl_WorkGroupID.x;
+  uint T = gl_LocalInvocationID.x;
+  // Each thread handles the elements T and T + B / 2 of the block, so that
+  // consecutive threads access consecutive elements
+  uint ai = T;
+  uint bi = T + B / 2;
+  uint i = W * B + ai;
+  uint j = W * B + bi;
+
+  uint result_a = uint(predicate(input_data.data[i]));
+  uint result_b = uint(predicate(input_data.data[j]));
+  offsets[padded(ai)] = result_a;
+  offsets[padded(bi)] = result_b;
+
+  // Perform the prefix sum on the flags, returning their total and replacing
+  // the last element with 0
+  uint sum = prefix_sum_return_total(T);
+  if (T == 0) {
+    output_data.sums[W] = sum;
+  }
+
+  output_data.offsets[i] = offsets[padded(ai)];
+  output_data.offsets[j] = offsets[padded(bi)];
+  output_data.results[i] = result_a;
+  output_data.results[j] = result_b;
+}
@@ -4,8 +4,8 @@
 
 // A single thread operates on two items at a time
 
-#define N -1337
-#define B -1337
+#define N
+#define B
 #define N_OVER_B (N / B)
 
 layout(local_size_x = B / 2, local_size_y = 1, local_size_z = 1) in;
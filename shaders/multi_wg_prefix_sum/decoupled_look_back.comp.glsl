@@ -0,0 +1,178 @@
+// Single-pass scan with decoupled look-back
+// https://research.nvidia.com/publication/2016-03_single-pass-parallel-prefix-scan-decoupled-look-back
+//
+// Each work group scans its block like multi_wg_prefix_sum1, publishes its
+// total, and then looks back at the work groups before it until it finds one
+// that already knows its inclusive prefix, instead of waiting for a second
+// dispatch. Blocks are handed out in the order the work groups start, so a
+// work group only ever waits for ones that are already running.
+#version 450 core
+// For TYPE int64_t and uint64_t, a warning where unsupported
+#extension GL_ARB_gpu_shader_int64 : enable
+
+// A single thread operates on two items at a time
+
+// See multi_wg_prefix_sum1
+#define N
+#define THREADS (N / 2)
+#define TYPE float
+#define OP a + b
+#define FUNCTIONS
+#define IDENTITY 0.0
+
+// The states of a block in `status`
+#define INVALID 0
+#define AGGREGATE_AVAILABLE 1
+#define PREFIX_AVAILABLE 2
+
+layout(local_size_x = THREADS, local_size_y = 1, local_size_z = 1) in;
+
+layout(std430, binding = 0) readonly buffer InputData { TYPE data[]; }
+input_data;
+
+layout(std430, binding = 1) writeonly buffer OutputData { TYPE data[]; }
+output_data;
+
+// The next block to scan, zeroed before the dispatch
+layout(std430, binding = 3) coherent buffer NextBlock { uint next_block; };
+
+// The state of each block, zeroed before the dispatch
+layout(std430, binding = 4) coherent buffer Status { uint flags[]; }
+status;
+
+// The total of each block, valid from AGGREGATE_AVAILABLE
+layout(std430, binding = 5) coherent buffer Aggregates { TYPE values[]; }
+aggregates;
+
+// The total of each block and all the ones before it, valid from
+// PREFIX_AVAILABLE
+layout(std430, binding = 6) coherent buffer InclusivePrefixes { TYPE values[]; }
+inclusive_prefixes;
+
+layout(location = 0) uniform uint len;
+layout(location = 1) uniform bool inclusive;
+
+shared TYPE block[N];
+shared uint W;
+// The total of the blocks before W
+shared TYPE block_prefix;
+
+FUNCTIONS
+TYPE combine(TYPE a, TYPE b) { return OP; }
+
+// Like prefix_sum_return_total in multi_wg_prefix_sum1, but only the shared
+// memory is accessed between the steps, so barrier() is enough
+TYPE prefix_sum_return_total(uint T) {
+  // **************************************************************************
+  // Reduce
+  uint offset = 1;
+
+  for (uint d = N >> 1; d > 0; d >>= 1) {
+    barrier();
+
+    if (T < d) {
+      uint ai = offset * (2 * T + 1) - 1;
+      uint bi = offset * (2 * T + 2) - 1;
+      block[bi] = combine(block[ai], block[bi]);
+    }
+
+    offset *= 2;
+  }
+
+  // **************************************************************************
+  // Down-sweep
+  barrier();
+  TYPE sum = block[N - 1];
+  barrier();
+  if (T == 0) {
+    block[N - 1] = IDENTITY;
+  }
+
+  for (uint d = 1; d < N; d *= 2) {
+    offset >>= 1;
+
+    barrier();
+
+    if (T < d) {
+      uint ai = offset * (2 * T + 1) - 1;
+      uint bi = offset * (2 * T + 2) - 1;
+
+      TYPE t = block[ai];
+
+      block[ai] = block[bi];
+      block[bi] = combine(block[bi], t);
+    }
+  }
+
+  barrier();
+  return sum;
+}
+
+// Publishes a value of block `w` and then its state, so that a work group that
+// sees the state also sees the value
+void publish(uint w, uint state) {
+  memoryBarrierBuffer();
+  atomicExchange(status.flags[w], state);
+}
+
+TYPE look_back(uint w) {
+  TYPE prefix = IDENTITY;
+  for (int j = int(w) - 1; j >= 0; j--) {
+    uint state;
+    do {
+      // An atomic read, so that the compiler reloads it every iteration
+      state = atomicAdd(status.flags[j], 0);
+    } while (state == INVALID);
+    memoryBarrierBuffer();
+
+    if (state == PREFIX_AVAILABLE) {
+      return combine(inclusive_prefixes.values[j], prefix);
+    }
+    prefix = combine(aggregates.values[j], prefix);
+  }
+  return prefix;
+}
+
+void main() {
+  uint T = gl_LocalInvocationID.x;
+
+  // gl_WorkGroupID.x could be scheduled in any order, waiting for a block
+  // whose work group has not started could deadlock
+  if (T == 0) {
+    W = atomicAdd(next_block, 1);
+  }
+  barrier();
+  uint i = (W * N) + (2 * T);
+
+  // The inputs are kept to turn the exclusive scan into an inclusive one
+  TYPE a = i < len ? input_data.data[i] : IDENTITY;
+  TYPE b = i + 1 < len ? input_data.data[i + 1] : IDENTITY;
+  block[2 * T] = a;
+  block[2 * T + 1] = b;
+
+  TYPE sum = prefix_sum_return_total(T);
+  if (T == 0) {
+    if (W == 0) {
+      inclusive_prefixes.values[0] = sum;
+      publish(0, PREFIX_AVAILABLE);
+      block_prefix = IDENTITY;
+    } else {
+      aggregates.values[W] = sum;
+      publish(W, AGGREGATE_AVAILABLE);
+
+      block_prefix = look_back(W);
+      inclusive_prefixes.values[W] = combine(block_prefix, sum);
+      publish(W, PREFIX_AVAILABLE);
+    }
+  }
+  barrier();
+
+  if (i < len) {
+    TYPE before = combine(block_prefix, block[2 * T]);
+    output_data.data[i] = inclusive ? combine(before, a) : before;
+  }
+  if (i + 1 < len) {
+    TYPE before = combine(block_prefix, block[2 * T + 1]);
+    output_data.data[i + 1] = inclusive ? combine(before, b) : before;
+  }
+}
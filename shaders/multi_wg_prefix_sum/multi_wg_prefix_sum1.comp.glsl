@@ -1,78 +1,59 @@
 // Blelloch parallel prefix sum/scan
 // https://developer.nvidia.com/gpugems/gpugems3/part-vi-gpu-computing/chapter-39-parallel-prefix-sum-scan-cuda
 #version 450 core
+// For TYPE int64_t and uint64_t, a warning where unsupported
+#extension GL_ARB_gpu_shader_int64 : enable
 
 // A single thread operates on two items at a time
 
-#define DATA_LEN -1337
-#define WORK_GROUPS -1337
-#define N (DATA_LEN / WORK_GROUPS)
+// The number of elements scanned by each work group, a power of two
+#define N
 #define THREADS (N / 2)
+// The type of the elements
+#define TYPE float
+// The associative operator the scan combines the elements with, an expression
+// of `a` and `b`, see combine
+#define OP a + b
+// Function definitions OP can call, usually empty
+#define FUNCTIONS
+// The identity of OP, which the last block is padded with, see 39.2.4 Arrays
+// of Arbitrary Size
+#define IDENTITY 0.0
 
 layout(local_size_x = THREADS, local_size_y = 1, local_size_z = 1) in;
 
-layout(std430, binding = 0) coherent buffer InputData { float data[N]; }
+layout(std430, binding = 0) readonly buffer InputData { TYPE data[]; }
 input_data;
 
-layout(std430, binding = 1) coherent buffer OutputData {
-  float sums[WORK_GROUPS];
-  float data[N];
-}
+layout(std430, binding = 1) writeonly buffer OutputData { TYPE data[]; }
 output_data;
 
-float prefix_sum_return_total(inout float offsets[N], uint T) {
-  float sum = -100.;
-  // **************************************************************************
-  // Reduce
-  uint offset = 1;
+// The total of each block, for multi_wg_prefix_sum2
+layout(std430, binding = 2) writeonly buffer BlockSums { TYPE sums[]; }
+block_sums;
 
-  for (uint d = N >> 1; d > 0; d >>= 1) {
-    barrier();
-    memoryBarrier(); // TODO: do I really need both of these? A. yes but maybe
-                     // not when I use shared memory instead
+// The number of elements in the input and output, which don't need to be a
+// multiple of N
+layout(location = 0) uniform uint len;
+// Whether each output element includes its own input, see ScanKind. The block
+// sums are always exclusive
+layout(location = 1) uniform bool inclusive;
 
-    if (T < d) {
-      uint ai = offset * (2 * T + 1) - 1;
-      uint bi = offset * (2 * T + 2) - 1;
-      offsets[bi] += offsets[ai];
-    }
+shared TYPE block[N];
 
-    offset *= 2;
-  }
+FUNCTIONS
 
-  // **************************************************************************
-  // Down-sweep
-  if (T == 0) {
-    sum = offsets[N - 1];
-    offsets[N - 1] = 0;
-  }
+// `a` is always the element that comes first, so OP does not need to be
+// commutative
+TYPE combine(TYPE a, TYPE b) { return OP; }
 
-  for (uint d = 1; d < N; d *= 2) {
-    offset >>= 1;
-
-    barrier();
-    memoryBarrier();
-
-    if (T < d) {
-      uint ai = offset * (2 * T + 1) - 1;
-      uint bi = offset * (2 * T + 2) - 1;
-
-      float t = offsets[ai];
-
-      offsets[ai] = offsets[bi];
-      offsets[bi] += t;
-    }
-  }
-
-  return sum;
-}
-
-void prefix_sum(inout float sums[WORK_GROUPS], uint T) {
+TYPE prefix_sum_return_total(uint T) {
+  TYPE sum = IDENTITY;
   // **************************************************************************
   // Reduce
   uint offset = 1;
 
-  for (uint d = WORK_GROUPS >> 1; d > 0; d >>= 1) {
+  for (uint d = N >> 1; d > 0; d >>= 1) {
     barrier();
     memoryBarrier(); // TODO: do I really need both of these? A. yes but maybe
                      // not when I use shared memory instead
@@ -80,7 +61,7 @@ void prefix_sum(inout float sums[WORK_GROUPS], uint T) {
     if (T < d) {
       uint ai = offset * (2 * T + 1) - 1;
       uint bi = offset * (2 * T + 2) - 1;
-      sums[bi] += sums[ai];
+      block[bi] = combine(block[ai], block[bi]);
     }
 
     offset *= 2;
@@ -89,10 +70,11 @@ void prefix_sum(inout float sums[WORK_GROUPS], uint T) {
   // **************************************************************************
   // Down-sweep
   if (T == 0) {
-    sums[WORK_GROUPS - 1] = 0;
+    sum = block[N - 1];
+    block[N - 1] = IDENTITY;
   }
 
-  for (uint d = 1; d < WORK_GROUPS; d *= 2) {
+  for (uint d = 1; d < N; d *= 2) {
     offset >>= 1;
 
     barrier();
@@ -102,42 +84,46 @@ void prefix_sum(inout float sums[WORK_GROUPS], uint T) {
       uint ai = offset * (2 * T + 1) - 1;
       uint bi = offset * (2 * T + 2) - 1;
 
-      float t = sums[ai];
+      TYPE t = block[ai];
 
-      sums[ai] = sums[bi];
-      sums[bi] += t;
+      block[ai] = block[bi];
+      block[bi] = combine(block[bi], t);
     }
   }
-}
 
-shared float block[N];
-shared float increments[WORK_GROUPS];
+  return sum;
+}
 
 void main() {
   uint W = gl_WorkGroupID.x;
   uint T = gl_LocalInvocationID.x;
-
-  // Copy global memory data into wg-shared data
-  block[2 * T] = input_data.data[(W * N) + (2 * T)];
-  block[2 * T + 1] = input_data.data[(W * N) + (2 * T + 1)];
+  uint i = (W * N) + (2 * T);
+
+  // Copy global memory data into wg-shared data, padding past the end. The
+  // inputs are kept to turn the exclusive scan into an inclusive one
+  TYPE a = i < len ? input_data.data[i] : IDENTITY;
+  TYPE b = i + 1 < len ? input_data.data[i + 1] : IDENTITY;
+  block[2 * T] = a;
+  block[2 * T + 1] = b;
   // Wait for the copy to be done
   barrier();
   memoryBarrier();
 
   // Perform the prefix sum on the input data, returning the total sum before
   // replacing the last element with 0
-  float sum = prefix_sum_return_total(block, T);
+  TYPE sum = prefix_sum_return_total(T);
   if (T == 0) {
-    output_data.sums[W] = sum;
+    block_sums.sums[W] = sum;
   }
   // Wait for the prefix sum and the write to be done
   barrier();
   memoryBarrier();
 
-  // Copy wg-shared data back to global memory
-  output_data.data[(W * N) + (2 * T)] = block[2 * T];
-  output_data.data[(W * N) + (2 * T + 1)] = block[2 * T + 1];
-  // Wait for the data to be copied
-  barrier();
-  memoryBarrier();
+  // Copy wg-shared data back to global memory, without the padding
+  if (i < len) {
+    output_data.data[i] = inclusive ? combine(block[2 * T], a) : block[2 * T];
+  }
+  if (i + 1 < len) {
+    output_data.data[i + 1] = inclusive ? combine(block[2 * T + 1], b) : block[2 * T + 1];
+  }
 }
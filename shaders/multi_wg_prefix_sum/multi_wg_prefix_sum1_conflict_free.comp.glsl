@@ -0,0 +1,125 @@
+// Blelloch parallel prefix sum/scan, avoiding shared memory bank conflicts
+// https://developer.nvidia.com/gpugems/gpugems3/part-vi-gpu-computing/chapter-39-parallel-prefix-sum-scan-cuda
+// 39.2.3 Avoiding Bank Conflicts
+//
+// A drop-in replacement for multi_wg_prefix_sum1, with the same parameters,
+// bindings and uniforms
+#version 450 core
+// For TYPE int64_t and uint64_t, a warning where unsupported
+#extension GL_ARB_gpu_shader_int64 : enable
+
+// A single thread operates on two items at a time
+
+// See multi_wg_prefix_sum1
+#define N
+#define THREADS (N / 2)
+#define TYPE float
+#define OP a + b
+#define FUNCTIONS
+#define IDENTITY 0.0
+// The number of shared memory banks is 1 << LOG_NUM_BANKS, 32 on current
+// NVIDIA and AMD GPUs
+#define LOG_NUM_BANKS 5
+
+layout(local_size_x = THREADS, local_size_y = 1, local_size_z = 1) in;
+
+layout(std430, binding = 0) readonly buffer InputData { TYPE data[]; }
+input_data;
+
+layout(std430, binding = 1) writeonly buffer OutputData { TYPE data[]; }
+output_data;
+
+layout(std430, binding = 2) writeonly buffer BlockSums { TYPE sums[]; }
+block_sums;
+
+layout(location = 0) uniform uint len;
+layout(location = 1) uniform bool inclusive;
+
+// One padding element every 1 << LOG_NUM_BANKS elements, so that the strided
+// accesses of the sweeps fall in different banks
+shared TYPE block[N + (N >> LOG_NUM_BANKS)];
+
+FUNCTIONS
+TYPE combine(TYPE a, TYPE b) { return OP; }
+
+uint padded(uint n) { return n + (n >> LOG_NUM_BANKS); }
+
+// Only the shared memory is accessed between the steps, so barrier() is enough:
+// it also orders the shared memory accesses of the work group, unlike the
+// SSBO ones that need a memoryBarrier()
+TYPE prefix_sum_return_total(uint T) {
+  // **************************************************************************
+  // Reduce
+  uint offset = 1;
+
+  for (uint d = N >> 1; d > 0; d >>= 1) {
+    barrier();
+
+    if (T < d) {
+      uint ai = padded(offset * (2 * T + 1) - 1);
+      uint bi = padded(offset * (2 * T + 2) - 1);
+      block[bi] = combine(block[ai], block[bi]);
+    }
+
+    offset *= 2;
+  }
+
+  // **************************************************************************
+  // Down-sweep
+  barrier();
+  TYPE sum = block[padded(N - 1)];
+  barrier();
+  if (T == 0) {
+    block[padded(N - 1)] = IDENTITY;
+  }
+
+  for (uint d = 1; d < N; d *= 2) {
+    offset >>= 1;
+
+    barrier();
+
+    if (T < d) {
+      uint ai = padded(offset * (2 * T + 1) - 1);
+      uint bi = padded(offset * (2 * T + 2) - 1);
+
+      TYPE t = block[ai];
+
+      block[ai] = block[bi];
+      block[bi] = combine(block[bi], t);
+    }
+  }
+
+  barrier();
+  return sum;
+}
+
+void main() {
+  uint W = gl_WorkGroupID.x;
+  uint T = gl_LocalInvocationID.x;
+  // Each thread loads the elements T and T + N / 2 of the block instead of 2T
+  // and 2T + 1, so that consecutive threads read consecutive elements
+  uint ai = T;
+  uint bi = T + THREADS;
+  uint i = W * N + ai;
+  uint j = W * N + bi;
+
+  // The inputs are kept to turn the exclusive scan into an inclusive one
+  TYPE a = i < len ? input_data.data[i] : IDENTITY;
+  TYPE b = j < len ? input_data.data[j] : IDENTITY;
+  block[padded(ai)] = a;
+  block[padded(bi)] = b;
+
+  TYPE sum = prefix_sum_return_total(T);
+  if (T == 0) {
+    block_sums.sums[W] = sum;
+  }
+
+  if (i < len) {
+    TYPE before = block[padded(ai)];
+    output_data.data[i] = inclusive ? combine(before, a) : before;
+  }
+  if (j < len) {
+    TYPE before = block[padded(bi)];
+    output_data.data[j] = inclusive ? combine(before, b) : before;
+  }
+}
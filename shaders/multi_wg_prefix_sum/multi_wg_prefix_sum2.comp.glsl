@@ -1,29 +1,43 @@
 // Blelloch parallel prefix sum/scan
 // https://developer.nvidia.com/gpugems/gpugems3/part-vi-gpu-computing/chapter-39-parallel-prefix-sum-scan-cuda
 #version 450 core
+// For TYPE int64_t and uint64_t, a warning where unsupported
+#extension GL_ARB_gpu_shader_int64 : enable
 
 // A single thread operates on two items at a time
 
-#define DATA_LEN -1337
-#define WORK_GROUPS -1337
-#define N (DATA_LEN / WORK_GROUPS)
+// The number of elements scanned by each work group, a power of two
+#define N
 #define THREADS (N / 2)
+// See multi_wg_prefix_sum1
+#define TYPE float
+#define OP a + b
+#define FUNCTIONS
 
 layout(local_size_x = THREADS, local_size_y = 1, local_size_z = 1) in;
 
-layout(std430, binding = 0) coherent buffer InputData { float data[N]; }
-input_data;
-
-layout(std430, binding = 1) coherent buffer OutputData {
-  float sums[WORK_GROUPS];
-  float data[N];
-}
+layout(std430, binding = 1) buffer OutputData { TYPE data[]; }
 output_data;
 
+// The exclusive prefix sum of the totals of the blocks
+layout(std430, binding = 2) readonly buffer BlockSums { TYPE sums[]; }
+block_sums;
+
+FUNCTIONS
+TYPE combine(TYPE a, TYPE b) { return OP; }
+
+layout(location = 0) uniform uint len;
+
 void main() {
   uint W = gl_WorkGroupID.x;
   uint T = gl_LocalInvocationID.x;
+  uint i = (W * N) + (2 * T);
 
-  output_data.data[(W * N) + (2 * T)] += output_data.sums[W];
-  output_data.data[(W * N) + (2 * T + 1)] += output_data.sums[W];
+  if (i < len) {
+    output_data.data[i] = combine(block_sums.sums[W], output_data.data[i]);
+  }
+  if (i + 1 < len) {
+    output_data.data[i + 1] =
+        combine(block_sums.sums[W], output_data.data[i + 1]);
+  }
 }
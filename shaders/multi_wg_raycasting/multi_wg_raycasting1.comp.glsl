@@ -2,12 +2,12 @@
 // https://developer.nvidia.com/gpugems/gpugems3/part-vi-gpu-computing/chapter-39-parallel-prefix-sum-scan-cuda
 #version 450 core
 
-#define CHUNK_X -1337
-#define CHUNK_Y -1337
-#define CHUNK_Z -1337
-#define CHUNK_SIZE -1337
-#define N -1337
-#define B -1337
+#define CHUNK_X
+#define CHUNK_Y
+#define CHUNK_Z
+#define CHUNK_SIZE
+#define N
+#define B
 #define N_OVER_B (N / B)
 #define MAX_ITERS 100
 
@@ -28,7 +28,18 @@ layout(std430, binding = 1) coherent buffer OutputData {
 }
 output_data;
 
-uint prefix_sum_return_total(inout uint offsets[B], uint T) {
+// CompactCounter, zeroed before the dispatch. Each work group adds its number
+// of hits to `len`, see compaction_flags
+layout(std430, binding = 2) buffer Counter {
+  uint len;
+  uvec3 num_groups;
+}
+counter;
+
+shared bool has_hit[B];
+shared uint offsets[B];
+
+uint prefix_sum_return_total(uint T) {
   uint sum = -100;
   // **************************************************************************
   // Reduce
@@ -118,9 +129,6 @@ void prefix_sum(inout uint sums[N_OVER_B], uint T) {
   }
 }
 
-shared bool has_hit[B];
-shared uint offsets[B];
-
 uvec4 raycast(vec3 ray_start, vec3 ray_direction_, out bool has_hit,
               in uint chunk[CHUNK_SIZE]) {
   vec3 ray_direction = normalize(ray_direction_ + vec3(1e-8, 1e-8, 1e-8));
@@ -198,9 +206,10 @@ void main() {
 
   // Perform the prefix sum on the input data, returning the total sum
   // replacing the last element with 0
-  uint sum = prefix_sum_return_total(offsets, T);
+  uint sum = prefix_sum_return_total(T);
   if (T == 0) {
     output_data.sums[W] = sum;
+    atomicAdd(counter.len, sum);
   }
   // Wait for the prefix sum and the write to be done
   barrier();
@@ -0,0 +1,185 @@
+// Blelloch parallel prefix sum/scan, avoiding shared memory bank conflicts
+// https://developer.nvidia.com/gpugems/gpugems3/part-vi-gpu-computing/chapter-39-parallel-prefix-sum-scan-cuda
+// 39.2.3 Avoiding Bank Conflicts
+//
+// A drop-in replacement for multi_wg_raycasting1, with the same parameters and
+// bindings. The hit flags stay in registers and only the offsets are scanned
+// in shared memory
+#version 450 core
+
+// See multi_wg_raycasting1
+#define CHUNK_X
+#define CHUNK_Y
+#define CHUNK_Z
+#define CHUNK_SIZE
+#define N
+#define B
+#define N_OVER_B (N / B)
+#define MAX_ITERS 100
+// See multi_wg_prefix_sum1_conflict_free
+#define LOG_NUM_BANKS 5
+
+layout(local_size_x = B / 2, local_size_y = 1, local_size_z = 1) in;
+
+layout(std430, binding = 0) readonly buffer InputData {
+  uint chunk[CHUNK_SIZE];
+  uvec4 ray_start;
+}
+input_data;
+
+layout(std430, binding = 1) writeonly buffer OutputData {
+  uint sums[N_OVER_B];
+  uint offsets[N];
+  uint has_hit[N];
+  uvec4 hits[N];
+  uvec4 compact_hits[N];
+}
+output_data;
+
+// See multi_wg_raycasting1
+layout(std430, binding = 2) buffer Counter {
+  uint len;
+  uvec3 num_groups;
+}
+counter;
+
+// One padding element every 1 << LOG_NUM_BANKS elements
+shared uint offsets[B + (B >> LOG_NUM_BANKS)];
+
+uint padded(uint n) { return n + (n >> LOG_NUM_BANKS); }
+
+// Only the shared memory is accessed between the steps, so barrier() is enough,
+// see multi_wg_prefix_sum1_conflict_free
+uint prefix_sum_return_total(uint T) {
+  // **************************************************************************
+  // Reduce
+  uint offset = 1;
+
+  for (uint d = B >> 1; d > 0; d >>= 1) {
+    barrier();
+
+    if (T < d) {
+      uint ai = padded(offset * (2 * T + 1) - 1);
+      uint bi = padded(offset * (2 * T + 2) - 1);
+      offsets[bi] += offsets[ai];
+    }
+
+    offset *= 2;
+  }
+
+  // **************************************************************************
+  // Down-sweep
+  barrier();
+  uint sum = offsets[padded(B - 1)];
+  barrier();
+  if (T == 0) {
+    offsets[padded(B - 1)] = 0;
+  }
+
+  for (uint d = 1; d < B; d *= 2) {
+    offset >>= 1;
+
+    barrier();
+
+    if (T < d) {
+      uint ai = padded(offset * (2 * T + 1) - 1);
+      uint bi = padded(offset * (2 * T + 2) - 1);
+
+      uint t = offsets[ai];
+
+      offsets[ai] = offsets[bi];
+      offsets[bi] += t;
+    }
+  }
+
+  barrier();
+  return sum;
+}
+
+// The chunk is read from input_data directly instead of being copied into a
+// parameter
+uvec4 raycast(vec3 ray_start, vec3 ray_direction_, out bool has_hit) {
+  vec3 ray_direction = normalize(ray_direction_ + vec3(1e-8, 1e-8, 1e-8));
+  vec3 ray_voxel = floor(ray_start);
+  vec3 step_ = sign(ray_direction);
+
+  vec3 t_max = ((ray_voxel + step_) - ray_start) / (ray_direction);
+  vec3 t_delta = (vec3(1., 1., 1.) / ray_direction) * step_;
+
+  has_hit = false;
+  for (int i = 0; i < MAX_ITERS; i++) {
+    // Traverse
+    if (t_max.x < t_max.y) {
+      if (t_max.x < t_max.z) {
+        ray_voxel.x += step_.x;
+        t_max.x += t_delta.x;
+      } else {
+        ray_voxel.z += step_.z;
+        t_max.z += t_delta.z;
+      }
+    } else {
+      if (t_max.y < t_max.z) {
+        ray_voxel.y += step_.y;
+        t_max.y += t_delta.y;
+      } else {
+        ray_voxel.z += step_.z;
+        t_max.z += t_delta.z;
+      }
+    }
+
+    // Check bounds
+    if (ray_voxel.x >= CHUNK_X || ray_voxel.x < 0)
+      break;
+    if (ray_voxel.y >= CHUNK_Y || ray_voxel.y < 0)
+      break;
+    if (ray_voxel.z >= CHUNK_Z || ray_voxel.z < 0)
+      break;
+
+    // Check if we are in a voxel full of data
+    int x = int(ray_voxel.x);
+    int y = int(ray_voxel.y);
+    int z = int(ray_voxel.z);
+    if (input_data.chunk[CHUNK_X * CHUNK_Y * z + CHUNK_X * y + x] == 1) {
+      // If we are, return the hit position
+      has_hit = true;
+      return uvec4(x, y, z, 1337);
+    }
+  }
+
+  return uvec4(-1, -1, -1, -1);
+}
+
+void main() {
+  uint W = gl_WorkGroupID.x;
+  uint T = gl_LocalInvocationID.x;
+  // Each thread casts the rays T and T + B / 2 of the block, so that
+  // consecutive threads access consecutive elements
+  uint ai = T;
+  uint bi = T + B / 2;
+  uint i = W * B + ai;
+  uint j = W * B + bi;
+
+  vec3 ray_start = vec3(3., 0., 0.);
+  vec3 ray_direction_a = vec3(2.0 * (-0.5 + float(i) / 7.0), 1.0, 0.0);
+  vec3 ray_direction_b = vec3(2.0 * (-0.5 + float(j) / 7.0), 1.0, 0.0);
+
+  bool has_hit_a;
+  bool has_hit_b;
+  output_data.hits[i] = raycast(ray_start, ray_direction_a, has_hit_a);
+  output_data.hits[j] = raycast(ray_start, ray_direction_b, has_hit_b);
+  offsets[padded(ai)] = uint(has_hit_a);
+  offsets[padded(bi)] = uint(has_hit_b);
+
+  // Perform the prefix sum on the hit flags, returning their total and
+  // replacing the last element with 0
+  uint sum = prefix_sum_return_total(T);
+  if (T == 0) {
+    output_data.sums[W] = sum;
+    atomicAdd(counter.len, sum);
+  }
+
+  output_data.offsets[i] = offsets[padded(ai)];
+  output_data.offsets[j] = offsets[padded(bi)];
+  output_data.has_hit[i] = uint(has_hit_a);
+  output_data.has_hit[j] = uint(has_hit_b);
+}
@@ -2,12 +2,12 @@
 // https://developer.nvidia.com/gpugems/gpugems3/part-vi-gpu-computing/chapter-39-parallel-prefix-sum-scan-cuda
 #version 450 core
 
-#define CHUNK_X -1337
-#define CHUNK_Y -1337
-#define CHUNK_Z -1337
-#define CHUNK_SIZE -1337
-#define N -1337
-#define B -1337
+#define CHUNK_X
+#define CHUNK_Y
+#define CHUNK_Z
+#define CHUNK_SIZE
+#define N
+#define B
 #define N_OVER_B (N / B)
 
 layout(local_size_x = B / 2, local_size_y = 1, local_size_z = 1) in;
@@ -0,0 +1,60 @@
+// The first step of a radix sort pass: counts how many keys of each work group
+// have each digit. The counts are then scanned to find where each work group
+// puts the keys of each digit, see radix_sort_scatter
+#version 450 core
+
+#define THREADS 256
+// The number of bits sorted by each pass, and the number of digits
+#define BITS 4
+#define RADIX (1 << BITS)
+
+layout(local_size_x = THREADS, local_size_y = 1, local_size_z = 1) in;
+
+layout(std430, binding = 0) readonly buffer Keys { uint keys[]; }
+keys;
+
+// The count of digit `d` in work group `W` is at `d * gl_NumWorkGroups.x + W`,
+// so that the exclusive scan of the counts orders the keys by digit first and
+// by work group second
+layout(std430, binding = 1) writeonly buffer Counts { uint counts[]; }
+counts;
+
+layout(location = 0) uniform uint len;
+// The position of the lowest bit of the digit sorted by this pass
+layout(location = 1) uniform uint shift;
+// How to turn the key bits into ones that sort like unsigned integers, see
+// RadixKey
+layout(location = 2) uniform uint key_kind;
+
+shared uint histogram[RADIX];
+
+uint sortable(uint bits) {
+  if (key_kind == 1) {
+    // int: negative numbers come first
+    return bits ^ 0x80000000u;
+  } else if (key_kind == 2) {
+    // float: negative numbers come first and in reverse order
+    return (bits & 0x80000000u) != 0 ? ~bits : bits ^ 0x80000000u;
+  }
+  return bits;
+}
+
+void main() {
+  uint i = gl_GlobalInvocationID.x;
+  uint T = gl_LocalInvocationIndex;
+
+  if (T < RADIX) {
+    histogram[T] = 0;
+  }
+  barrier();
+
+  if (i < len) {
+    uint digit = (sortable(keys.keys[i]) >> shift) & (RADIX - 1);
+    atomicAdd(histogram[digit], 1);
+  }
+  barrier();
+
+  if (T < RADIX) {
+    counts.counts[T * gl_NumWorkGroups.x + gl_WorkGroupID.x] = histogram[T];
+  }
+}
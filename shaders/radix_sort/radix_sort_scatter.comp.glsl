@@ -0,0 +1,72 @@
+// The second step of a radix sort pass: moves every key, and its payload, to
+// the offset of its digit in its work group plus the number of keys with the
+// same digit before it in the work group, which keeps the sort stable
+#version 450 core
+
+#define THREADS 256
+// Must match radix_sort_histogram
+#define BITS 4
+#define RADIX (1 << BITS)
+
+layout(local_size_x = THREADS, local_size_y = 1, local_size_z = 1) in;
+
+layout(std430, binding = 0) readonly buffer InputKeys { uint keys[]; }
+input_keys;
+
+// The exclusive scan of the counts of radix_sort_histogram
+layout(std430, binding = 1) readonly buffer Offsets { uint offsets[]; }
+offsets;
+
+layout(std430, binding = 2) writeonly buffer OutputKeys { uint keys[]; }
+output_keys;
+
+// Only accessed when payload_words is not 0
+layout(std430, binding = 3) readonly buffer InputPayload { uint words[]; }
+input_payload;
+
+layout(std430, binding = 4) writeonly buffer OutputPayload { uint words[]; }
+output_payload;
+
+layout(location = 0) uniform uint len;
+layout(location = 1) uniform uint shift;
+layout(location = 2) uniform uint key_kind;
+// The array stride of the payload, in words, or 0 without a payload
+layout(location = 3) uniform uint payload_words;
+
+// The digit of each key of the work group, RADIX past the end
+shared uint digits[THREADS];
+
+uint sortable(uint bits) {
+  if (key_kind == 1) {
+    return bits ^ 0x80000000u;
+  } else if (key_kind == 2) {
+    return (bits & 0x80000000u) != 0 ? ~bits : bits ^ 0x80000000u;
+  }
+  return bits;
+}
+
+void main() {
+  uint i = gl_GlobalInvocationID.x;
+  uint T = gl_LocalInvocationIndex;
+
+  uint key = i < len ? input_keys.keys[i] : 0;
+  uint digit = i < len ? (sortable(key) >> shift) & (RADIX - 1) : RADIX;
+  digits[T] = digit;
+  barrier();
+
+  if (i < len) {
+    // Counting the earlier keys directly costs as much as scanning a flag per
+    // digit would, with RADIX = 16 and THREADS = 256
+    uint rank = 0;
+    for (uint t = 0; t < T; t++) {
+      rank += uint(digits[t] == digit);
+    }
+    uint to = offsets.offsets[digit * gl_NumWorkGroups.x + gl_WorkGroupID.x] + rank;
+
+    output_keys.keys[to] = key;
+    for (uint word = 0; word < payload_words; word++) {
+      output_payload.words[to * payload_words + word] =
+          input_payload.words[i * payload_words + word];
+    }
+  }
+}
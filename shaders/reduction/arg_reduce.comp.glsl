@@ -0,0 +1,83 @@
+// Like reduce, keeping the index of the best element along with its value
+#version 450 core
+// For TYPE int64_t and uint64_t, a warning where unsupported
+#extension GL_ARB_gpu_shader_int64 : enable
+
+// The number of elements reduced by each work group, a power of two
+#define N
+#define THREADS (N / 2)
+// The type of the elements, a scalar
+#define TYPE float
+// Whether `a` is strictly better than `b`, e.g. `a < b` for the minimum
+#define BETTER a < b
+// A value no element is better than, which the last block is padded with
+#define WORST 0.0
+
+// The index of the padding
+#define NO_INDEX 0xFFFFFFFFu
+
+layout(local_size_x = THREADS, local_size_y = 1, local_size_z = 1) in;
+
+layout(std430, binding = 0) readonly buffer InputData { TYPE data[]; }
+input_data;
+
+layout(std430, binding = 1) writeonly buffer Partials { TYPE partials[]; }
+partials;
+
+// The indices of the input elements, when the input is itself partials
+layout(std430, binding = 2) readonly buffer InputIndices { uint indices[]; }
+input_indices;
+
+layout(std430, binding = 3) writeonly buffer PartialIndices { uint indices[]; }
+partial_indices;
+
+layout(location = 0) uniform uint len;
+// Whether to read the indices from input_indices, instead of using the
+// positions of the elements
+layout(location = 1) uniform bool indexed;
+
+shared TYPE values[THREADS];
+shared uint indices[THREADS];
+
+bool better(TYPE a, TYPE b) { return BETTER; }
+
+// `a` comes first, so ties keep the smallest index
+void pick(inout TYPE a, inout uint a_index, TYPE b, uint b_index) {
+  if (better(b, a)) {
+    a = b;
+    a_index = b_index;
+  }
+}
+
+uint index_of(uint i) { return indexed ? input_indices.indices[i] : i; }
+
+void main() {
+  uint W = gl_WorkGroupID.x;
+  uint T = gl_LocalInvocationID.x;
+  uint i = (W * N) + (2 * T);
+
+  TYPE a = i < len ? input_data.data[i] : WORST;
+  uint a_index = i < len ? index_of(i) : NO_INDEX;
+  TYPE b = i + 1 < len ? input_data.data[i + 1] : WORST;
+  uint b_index = i + 1 < len ? index_of(i + 1) : NO_INDEX;
+  pick(a, a_index, b, b_index);
+  values[T] = a;
+  indices[T] = a_index;
+  barrier();
+
+  for (uint stride = 1; stride < THREADS; stride *= 2) {
+    if (T % (2 * stride) == 0) {
+      TYPE value = values[T];
+      uint index = indices[T];
+      pick(value, index, values[T + stride], indices[T + stride]);
+      values[T] = value;
+      indices[T] = index;
+    }
+    barrier();
+  }
+
+  if (T == 0) {
+    partials.partials[W] = values[0];
+    partial_indices.indices[W] = indices[0];
+  }
+}
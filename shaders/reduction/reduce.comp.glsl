@@ -0,0 +1,63 @@
+// Tree reduction: every work group combines N elements into one partial, and
+// the partials are reduced again until one is left
+// https://developer.download.nvidia.com/assets/cuda/files/reduction.pdf
+#version 450 core
+// For TYPE int64_t and uint64_t, a warning where unsupported
+#extension GL_ARB_gpu_shader_int64 : enable
+
+// A single thread combines two elements while loading them
+
+// The number of elements reduced by each work group, a power of two
+#define N
+#define THREADS (N / 2)
+// The type of the elements
+#define TYPE float
+// The associative operator, an expression of `a` and `b`, see combine
+#define OP a + b
+// Function definitions OP can call, usually empty
+#define FUNCTIONS
+// The identity of OP, which the last block is padded with
+#define IDENTITY 0.0
+
+layout(local_size_x = THREADS, local_size_y = 1, local_size_z = 1) in;
+
+layout(std430, binding = 0) readonly buffer InputData { TYPE data[]; }
+input_data;
+
+// One partial per work group
+layout(std430, binding = 1) writeonly buffer Partials { TYPE partials[]; }
+partials;
+
+layout(location = 0) uniform uint len;
+
+shared TYPE block[THREADS];
+
+FUNCTIONS
+
+// `a` is always the element that comes first, so OP does not need to be
+// commutative
+TYPE combine(TYPE a, TYPE b) { return OP; }
+
+void main() {
+  uint W = gl_WorkGroupID.x;
+  uint T = gl_LocalInvocationID.x;
+  uint i = (W * N) + (2 * T);
+
+  TYPE a = i < len ? input_data.data[i] : IDENTITY;
+  TYPE b = i + 1 < len ? input_data.data[i + 1] : IDENTITY;
+  block[T] = combine(a, b);
+  barrier();
+
+  // Interleaved rather than sequential addressing, so that every partial
+  // covers consecutive elements
+  for (uint stride = 1; stride < THREADS; stride *= 2) {
+    if (T % (2 * stride) == 0) {
+      block[T] = combine(block[T], block[T + stride]);
+    }
+    barrier();
+  }
+
+  if (T == 0) {
+    partials.partials[W] = block[0];
+  }
+}
@@ -0,0 +1,29 @@
+// Turns the end of every run, from run_ends, into its length. Dispatched
+// indirectly with one thread per run
+#version 450 core
+
+#define THREADS 256
+
+layout(local_size_x = THREADS, local_size_y = 1, local_size_z = 1) in;
+
+// One past the index of the last element of each run
+layout(std430, binding = 0) readonly buffer Ends { uint ends[]; }
+ends;
+
+layout(std430, binding = 1) writeonly buffer Counts { uint counts[]; }
+counts;
+
+// CompactCounter, with the number of runs
+layout(std430, binding = 2) readonly buffer Counter {
+  uint len;
+  uvec3 num_groups;
+}
+counter;
+
+void main() {
+  uint run = gl_GlobalInvocationID.x;
+
+  if (run < counter.len) {
+    counts.counts[run] = ends.ends[run] - (run == 0 ? 0 : ends.ends[run - 1]);
+  }
+}
@@ -0,0 +1,38 @@
+// Writes a value for every run of equal keys, from the last element of the
+// run. The heads of the runs are flagged and scanned like in stream
+// compaction, so the run of element `i` is its inclusive scan minus one
+#version 450 core
+// For TYPE int64_t and uint64_t, a warning where unsupported
+#extension GL_ARB_gpu_shader_int64 : enable
+
+#define THREADS 256
+// The type of the values
+#define TYPE uint
+// The value of a run, an expression of the index `i` of its last element, e.g.
+// the inclusive segmented scan of the values at `i` to reduce them by key
+#define VALUE input_values.data[i]
+
+layout(local_size_x = THREADS, local_size_y = 1, local_size_z = 1) in;
+
+layout(std430, binding = 1) readonly buffer Flags { uint flags[]; }
+heads;
+
+layout(std430, binding = 2) readonly buffer Offsets { uint offsets[]; }
+offsets;
+
+layout(std430, binding = 3) readonly buffer InputValues { TYPE data[]; }
+input_values;
+
+layout(std430, binding = 4) writeonly buffer OutputValues { TYPE data[]; }
+output_values;
+
+layout(location = 0) uniform uint len;
+
+void main() {
+  uint i = gl_GlobalInvocationID.x;
+
+  if (i < len && (i + 1 == len || heads.flags[i + 1] != 0)) {
+    uint run = offsets.offsets[i] + heads.flags[i] - 1;
+    output_values.data[run] = VALUE;
+  }
+}
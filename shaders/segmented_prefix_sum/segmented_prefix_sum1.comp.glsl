@@ -0,0 +1,170 @@
+// Blelloch parallel segmented prefix sum/scan, which restarts at every head
+// flag. The scan runs on (flag, value) pairs, where the pair operator keeps the
+// right value alone if its flag is set, see combine_pair
+// https://developer.nvidia.com/gpugems/gpugems3/part-vi-gpu-computing/chapter-39-parallel-prefix-sum-scan-cuda
+#version 450 core
+// For TYPE int64_t and uint64_t, a warning where unsupported
+#extension GL_ARB_gpu_shader_int64 : enable
+
+// A single thread operates on two items at a time
+
+// See multi_wg_prefix_sum1
+#define N
+#define THREADS (N / 2)
+#define TYPE float
+#define OP a + b
+#define FUNCTIONS
+#define IDENTITY 0.0
+
+// The values of `mode`
+#define INCLUSIVE 0
+#define EXCLUSIVE 1
+// The exclusive scan of the pairs, without resetting the heads themselves to
+// the identity. Block W needs this for the block sums, since the elements
+// before the first head of block W still continue the segment of block W - 1
+#define CARRY 2
+
+layout(local_size_x = THREADS, local_size_y = 1, local_size_z = 1) in;
+
+layout(std430, binding = 0) readonly buffer InputData { TYPE data[]; }
+input_data;
+
+layout(std430, binding = 1) writeonly buffer OutputData { TYPE data[]; }
+output_data;
+
+// The total of each block since its last head, for segmented_prefix_sum2
+layout(std430, binding = 2) writeonly buffer BlockSums { TYPE sums[]; }
+block_sums;
+
+// Non-zero where a segment starts
+layout(std430, binding = 3) readonly buffer HeadFlags { uint flags[]; }
+head_flags;
+
+// Whether each block has a head, the flags of the block sums
+layout(std430, binding = 4) writeonly buffer BlockFlags { uint flags[]; }
+block_flags;
+
+// Whether each element still needs the block sums of the blocks before it,
+// i.e. it has no head before it in its block
+layout(std430, binding = 5) writeonly buffer NeedsCarry { uint flags[]; }
+needs_carry;
+
+layout(location = 0) uniform uint len;
+// INCLUSIVE, EXCLUSIVE or CARRY
+layout(location = 1) uniform uint mode;
+
+shared TYPE block[N];
+shared uint flags[N];
+
+FUNCTIONS
+TYPE combine(TYPE a, TYPE b) { return OP; }
+
+// The segmented operator on the pair at `i` and the pair `(flag, value)`,
+// which comes after it, stored back at `i`
+void combine_pair(uint i, uint flag, TYPE value) {
+  block[i] = flag != 0 ? value : combine(block[i], value);
+  flags[i] |= flag;
+}
+
+// Like prefix_sum_return_total in multi_wg_prefix_sum1, on the pairs
+void prefix_sum_return_total(uint T, out uint total_flag, out TYPE total) {
+  // **************************************************************************
+  // Reduce
+  uint offset = 1;
+
+  for (uint d = N >> 1; d > 0; d >>= 1) {
+    barrier();
+
+    if (T < d) {
+      uint ai = offset * (2 * T + 1) - 1;
+      uint bi = offset * (2 * T + 2) - 1;
+
+      uint flag = flags[bi];
+      TYPE value = block[bi];
+      block[bi] = block[ai];
+      flags[bi] = flags[ai];
+      combine_pair(bi, flag, value);
+    }
+
+    offset *= 2;
+  }
+
+  // **************************************************************************
+  // Down-sweep
+  barrier();
+  total_flag = flags[N - 1];
+  total = block[N - 1];
+  barrier();
+  if (T == 0) {
+    flags[N - 1] = 0;
+    block[N - 1] = IDENTITY;
+  }
+
+  for (uint d = 1; d < N; d *= 2) {
+    offset >>= 1;
+
+    barrier();
+
+    if (T < d) {
+      uint ai = offset * (2 * T + 1) - 1;
+      uint bi = offset * (2 * T + 2) - 1;
+
+      uint t_flag = flags[ai];
+      TYPE t = block[ai];
+
+      flags[ai] = flags[bi];
+      block[ai] = block[bi];
+      combine_pair(bi, t_flag, t);
+    }
+  }
+
+  barrier();
+}
+
+// The output of an element with the exclusive scan `(before_flag, before)`
+// and the input `(flag, value)`, and whether it needs the carry
+void finish(uint i, uint before_flag, TYPE before, uint flag, TYPE value) {
+  if (i >= len) {
+    return;
+  }
+
+  if (mode == CARRY) {
+    output_data.data[i] = before;
+    needs_carry.flags[i] = uint(before_flag == 0);
+  } else {
+    if (mode == INCLUSIVE) {
+      output_data.data[i] = flag != 0 ? value : combine(before, value);
+    } else {
+      output_data.data[i] = flag != 0 ? IDENTITY : before;
+    }
+    needs_carry.flags[i] = uint(before_flag == 0 && flag == 0);
+  }
+}
+
+void main() {
+  uint W = gl_WorkGroupID.x;
+  uint T = gl_LocalInvocationID.x;
+  uint i = (W * N) + (2 * T);
+
+  // Copy global memory data into wg-shared data, padding past the end. The
+  // inputs are kept for the inclusive scan and the heads
+  TYPE a = i < len ? input_data.data[i] : IDENTITY;
+  TYPE b = i + 1 < len ? input_data.data[i + 1] : IDENTITY;
+  uint a_flag = i < len ? uint(head_flags.flags[i] != 0) : 0;
+  uint b_flag = i + 1 < len ? uint(head_flags.flags[i + 1] != 0) : 0;
+  block[2 * T] = a;
+  block[2 * T + 1] = b;
+  flags[2 * T] = a_flag;
+  flags[2 * T + 1] = b_flag;
+
+  uint total_flag;
+  TYPE total;
+  prefix_sum_return_total(T, total_flag, total);
+  if (T == 0) {
+    block_sums.sums[W] = total;
+    block_flags.flags[W] = total_flag;
+  }
+
+  finish(i, flags[2 * T], block[2 * T], a_flag, a);
+  finish(i + 1, flags[2 * T + 1], block[2 * T + 1], b_flag, b);
+}
@@ -0,0 +1,46 @@
+// Adds the segmented scan of the block sums to the elements of each block that
+// continue the segment of the block before it
+#version 450 core
+// For TYPE int64_t and uint64_t, a warning where unsupported
+#extension GL_ARB_gpu_shader_int64 : enable
+
+// A single thread operates on two items at a time
+
+// See multi_wg_prefix_sum1
+#define N
+#define THREADS (N / 2)
+#define TYPE float
+#define OP a + b
+#define FUNCTIONS
+
+layout(local_size_x = THREADS, local_size_y = 1, local_size_z = 1) in;
+
+layout(std430, binding = 1) buffer OutputData { TYPE data[]; }
+output_data;
+
+// The segmented exclusive scan of the totals of the blocks since their last
+// head, in CARRY mode
+layout(std430, binding = 2) readonly buffer BlockSums { TYPE sums[]; }
+block_sums;
+
+layout(std430, binding = 5) readonly buffer NeedsCarry { uint flags[]; }
+needs_carry;
+
+layout(location = 0) uniform uint len;
+
+FUNCTIONS
+TYPE combine(TYPE a, TYPE b) { return OP; }
+
+void main() {
+  uint W = gl_WorkGroupID.x;
+  uint T = gl_LocalInvocationID.x;
+  uint i = (W * N) + (2 * T);
+
+  if (i < len && needs_carry.flags[i] != 0) {
+    output_data.data[i] = combine(block_sums.sums[W], output_data.data[i]);
+  }
+  if (i + 1 < len && needs_carry.flags[i + 1] != 0) {
+    output_data.data[i + 1] =
+        combine(block_sums.sums[W], output_data.data[i + 1]);
+  }
+}
@@ -1,10 +1,12 @@
 // Blelloch parallel prefix sum/scan
 // https://developer.nvidia.com/gpugems/gpugems3/part-vi-gpu-computing/chapter-39-parallel-prefix-sum-scan-cuda
-#version 460 core
+#version 450 core
 
 // A single thread operates on two items at a time
-#define N -1337
+#define N
 #define THREADS (N / 2)
+// Which elements to keep, a boolean expression of the element `x`
+#define PREDICATE int(x) % 2 == 0
 
 layout(local_size_x = THREADS, local_size_y = 1, local_size_z = 1) in;
 
@@ -15,14 +17,24 @@ layout(std430, binding = 0) restrict coherent buffer InputData {
 input_data;
 
 layout(std430, binding = 1) restrict coherent buffer OutputData {
-  uint length;
   float data[N];
 }
 output_data;
 
-// TODO: Verify that this compiles to a pass-by-reference and not a deepcopy. Or
-// at least gets inlined
-void prefix_sum2(inout uint offsets[N], uint tid) {
+// CompactCounter, zeroed before the dispatch. Only `len` is written here, see
+// compaction_flags
+layout(std430, binding = 2) buffer Counter {
+  uint len;
+  uvec3 num_groups;
+}
+counter;
+
+shared bool results[N];
+shared uint offsets[N];
+// The number of kept elements, added to the counter with one global atomic
+shared uint kept;
+
+void prefix_sum2(uint tid) {
   // **************************************************************************
   // Reduce
   uint offset = 1;
@@ -68,23 +80,26 @@ void prefix_sum2(inout uint offsets[N], uint tid) {
   memoryBarrier();
 }
 
-shared bool results[N];
-shared uint offsets[N];
-
-bool predicate(float x) { return int(x) % 2 == 0; }
+bool predicate(float x) { return PREDICATE; }
 
 // FIXME(Andrea): there is some kind of bug in the first element
 
 void main() {
   uint tid = gl_LocalInvocationID.x;
 
+  if (tid == 0) {
+    kept = 0;
+  }
+  barrier();
+
   results[2 * tid] = predicate(input_data.data[2 * tid]);
   results[2 * tid + 1] = predicate(input_data.data[2 * tid + 1]);
+  atomicAdd(kept, uint(results[2 * tid]) + uint(results[2 * tid + 1]));
 
   offsets[2 * tid] = uint(results[2 * tid]);
   offsets[2 * tid + 1] = uint(results[2 * tid + 1]);
 
-  prefix_sum2(offsets, tid);
+  prefix_sum2(tid);
 
   if (results[2 * tid]) {
     output_data.data[offsets[2 * tid]] = input_data.data[2 * tid];
@@ -94,15 +109,8 @@ void main() {
   }
 
   barrier();
-  memoryBarrier();
 
   if (tid == 0) {
-    // TODO(Andrea): Could this be done better? Maybe with an atomic?
-    for (uint i = N - 1; i >= 0; i--) {
-      if (results[i]) {
-        output_data.length = offsets[i] + 1;
-        break;
-      }
-    }
+    atomicAdd(counter.len, kept);
   }
 }
@@ -1,9 +1,9 @@
 // Blelloch parallel prefix sum/scan
 // https://developer.nvidia.com/gpugems/gpugems3/part-vi-gpu-computing/chapter-39-parallel-prefix-sum-scan-cuda
-#version 460 core
+#version 450 core
 
 // A single thread operates on two items at a time
-#define N -1337
+#define N
 #define THREADS (N / 2)
 
 layout(local_size_x = THREADS, local_size_y = 1, local_size_z = 1) in;
@@ -1,10 +1,10 @@
-#version 460 core
+#version 450 core
 
-#define CHUNK_ROWS -1337
-#define CHUNK_COLS -1337
+#define CHUNK_ROWS
+#define CHUNK_COLS
 #define CHUNK_SIZE (CHUNK_ROWS * CHUNK_COLS)
-#define MAX_ITERS -1337
-#define THREADS -1337
+#define MAX_ITERS
+#define THREADS
 
 layout(local_size_x = THREADS, local_size_y = 1, local_size_z = 1) in;
 
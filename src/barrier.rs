@@ -0,0 +1,160 @@
+use gl::types::*;
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+/// How a buffer is about to be accessed, which decides the `glMemoryBarrier`
+/// bit needed to see earlier shader writes to it.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum BufferAccess {
+    /// From a shader, through a `buffer` block.
+    ShaderStorage,
+    /// From GL commands like `glBufferSubData`, `glCopyBufferSubData` or a
+    /// (non-persistent) `glMapBufferRange`.
+    Update,
+    /// From the host through a persistent mapping.
+    ClientMapped,
+    /// As the parameters of `glDispatchComputeIndirect`.
+    Command,
+}
+
+impl BufferAccess {
+    fn barrier_bit(self) -> GLbitfield {
+        match self {
+            BufferAccess::ShaderStorage => gl::SHADER_STORAGE_BARRIER_BIT,
+            BufferAccess::Update => gl::BUFFER_UPDATE_BARRIER_BIT,
+            BufferAccess::ClientMapped => gl::CLIENT_MAPPED_BUFFER_BARRIER_BIT,
+            BufferAccess::Command => gl::COMMAND_BARRIER_BIT,
+        }
+    }
+}
+
+// Shader writes are incoherent with everything, so a buffer written by a
+// dispatch needs one barrier bit per kind of access that comes after it.
+// A barrier is global: once issued, it covers every buffer written before it.
+#[derive(Debug, Default)]
+struct BarrierTracker {
+    /// The buffer bound to each `GL_SHADER_STORAGE_BUFFER` binding point.
+    bindings: HashMap<GLuint, GLuint>,
+    /// The barrier bits each buffer still needs, by buffer id.
+    pending: HashMap<GLuint, GLbitfield>,
+}
+
+const ALL_BUFFER_BARRIER_BITS: GLbitfield = gl::SHADER_STORAGE_BARRIER_BIT
+    | gl::BUFFER_UPDATE_BARRIER_BIT
+    | gl::CLIENT_MAPPED_BUFFER_BARRIER_BIT
+    | gl::COMMAND_BARRIER_BIT;
+
+impl BarrierTracker {
+    fn bind(&mut self, index: GLuint, buffer: GLuint) {
+        self.bindings.insert(index, buffer);
+    }
+
+    fn delete(&mut self, buffer: GLuint) {
+        self.pending.remove(&buffer);
+        self.bindings.retain(|_, bound| *bound != buffer);
+    }
+
+    /// Returns the barrier bit to issue before `access` to `buffer`, 0 if none.
+    fn before(&mut self, buffer: GLuint, access: BufferAccess) -> GLbitfield {
+        let bit = access.barrier_bit();
+        if self.pending.get(&buffer).map_or(0, |bits| bits & bit) == 0 {
+            return 0;
+        }
+        for bits in self.pending.values_mut() {
+            *bits &= !bit;
+        }
+        self.pending.retain(|_, bits| *bits != 0);
+        bit
+    }
+
+    /// Returns the barrier bits to issue before a dispatch that uses the blocks
+    /// at `bindings`, and marks the buffers bound there as written.
+    fn dispatch(&mut self, bindings: &[GLuint]) -> GLbitfield {
+        let buffers: Vec<GLuint> = bindings
+            .iter()
+            .filter_map(|index| self.bindings.get(index).copied())
+            .collect();
+
+        let mut bits = 0;
+        for &buffer in &buffers {
+            bits |= self.before(buffer, BufferAccess::ShaderStorage);
+        }
+        // Readonly blocks are not visible through reflection, so every buffer
+        // the program can see is assumed to be written
+        for buffer in buffers {
+            self.pending.insert(buffer, ALL_BUFFER_BARRIER_BITS);
+        }
+        bits
+    }
+}
+
+thread_local! {
+    // GL contexts are current on a single thread, and so are the buffers
+    static TRACKER: RefCell<BarrierTracker> = RefCell::new(BarrierTracker::default());
+}
+
+pub(crate) fn bind(index: GLuint, buffer: GLuint) {
+    TRACKER.with(|tracker| tracker.borrow_mut().bind(index, buffer));
+}
+
+pub(crate) fn delete(buffer: GLuint) {
+    TRACKER.with(|tracker| tracker.borrow_mut().delete(buffer));
+}
+
+/// Issues the barrier needed before `access` to `buffer`, if any.
+pub(crate) fn before(buffer: GLuint, access: BufferAccess) {
+    let bits = TRACKER.with(|tracker| tracker.borrow_mut().before(buffer, access));
+    if bits != 0 {
+        unsafe { gl::MemoryBarrier(bits) };
+    }
+}
+
+/// Issues the barriers needed before a dispatch using the blocks at
+/// `bindings`.
+pub(crate) fn dispatch(bindings: &[GLuint]) {
+    let bits = TRACKER.with(|tracker| tracker.borrow_mut().dispatch(bindings));
+    if bits != 0 {
+        unsafe { gl::MemoryBarrier(bits) };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BarrierTracker, BufferAccess};
+
+    #[test]
+    fn test_barrier_tracker() {
+        let mut tracker = BarrierTracker::default();
+        tracker.bind(0, 10);
+        tracker.bind(1, 11);
+
+        // Nothing was written by a shader yet
+        assert_eq!(tracker.before(10, BufferAccess::Update), 0);
+        assert_eq!(tracker.dispatch(&[0, 1]), 0);
+
+        // The second kernel reads what the first one wrote
+        assert_eq!(tracker.dispatch(&[0, 1]), gl::SHADER_STORAGE_BARRIER_BIT);
+
+        // Reading back needs its own bit, once for all buffers
+        assert_eq!(
+            tracker.before(11, BufferAccess::Update),
+            gl::BUFFER_UPDATE_BARRIER_BIT
+        );
+        assert_eq!(tracker.before(10, BufferAccess::Update), 0);
+        assert_eq!(
+            tracker.before(10, BufferAccess::ClientMapped),
+            gl::CLIENT_MAPPED_BUFFER_BARRIER_BIT
+        );
+
+        // A kernel only waits for the buffers it uses, but the barrier covers
+        // every buffer written before it
+        tracker.bind(2, 12);
+        assert_eq!(tracker.dispatch(&[2]), 0);
+        assert_eq!(tracker.dispatch(&[0]), gl::SHADER_STORAGE_BARRIER_BIT);
+        assert_eq!(tracker.before(12, BufferAccess::ShaderStorage), 0);
+
+        tracker.delete(10);
+        assert_eq!(tracker.before(10, BufferAccess::Update), 0);
+        assert_eq!(tracker.dispatch(&[0]), 0);
+    }
+}
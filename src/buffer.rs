@@ -0,0 +1,196 @@
+use gl::types::*;
+use std::marker::PhantomData;
+use std::ops::Range;
+
+use crate::barrier::{self, BufferAccess};
+use crate::Std430;
+
+/// A shader storage buffer holding `len` elements of type `T`, laid out like
+/// the GLSL array `T data[len]` in a std430 block. The GL object is deleted on
+/// drop.
+pub struct Buffer<T: Std430> {
+    id: GLuint,
+    len: usize,
+    _marker: PhantomData<T>,
+    /// GL objects belong to the thread whose context is current, so buffers
+    /// are neither `Send` nor `Sync`.
+    _not_send: PhantomData<*const ()>,
+}
+
+impl<T: Std430> Buffer<T> {
+    /// Allocates a zero-filled buffer of `len` elements.
+    pub fn new(len: usize) -> Self {
+        let buffer = Self::allocate(len, std::ptr::null());
+        unsafe {
+            gl::ClearNamedBufferData(
+                buffer.id,
+                gl::R8UI,
+                gl::RED_INTEGER,
+                gl::UNSIGNED_BYTE,
+                std::ptr::null(),
+            );
+        }
+        buffer
+    }
+
+    /// Allocates a buffer and uploads `data` into it.
+    pub fn from_slice(data: &[T]) -> Self {
+        let bytes = to_std430_bytes(data);
+        Self::allocate(data.len(), bytes.as_ptr() as *const GLvoid)
+    }
+
+    fn allocate(len: usize, data: *const GLvoid) -> Self {
+        let mut id = 0;
+        unsafe {
+            gl::CreateBuffers(1, &mut id);
+            gl::NamedBufferData(id, (len * T::STRIDE) as GLsizeiptr, data, gl::DYNAMIC_READ);
+        }
+        Buffer {
+            id,
+            len,
+            _marker: PhantomData,
+            _not_send: PhantomData,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn id(&self) -> GLuint {
+        self.id
+    }
+
+    /// Binds the whole buffer to the `binding = index` shader storage block.
+    pub fn bind(&self, index: GLuint) {
+        unsafe { gl::BindBufferBase(gl::SHADER_STORAGE_BUFFER, index, self.id) };
+        barrier::bind(index, self.id);
+    }
+
+    /// Issues the memory barrier needed to see the writes of earlier
+    /// dispatches through `access`. The methods of `Buffer` and
+    /// `Program::dispatch` already do, this is for accesses through `id`.
+    pub fn prepare(&self, access: BufferAccess) {
+        barrier::before(self.id, access);
+    }
+
+    /// Overwrites the elements starting at `offset` with `data`.
+    pub fn write(&mut self, offset: usize, data: &[T]) {
+        assert!(
+            offset + data.len() <= self.len,
+            "write of {} elements at {} is out of bounds for a buffer of {}",
+            data.len(),
+            offset,
+            self.len
+        );
+        let bytes = to_std430_bytes(data);
+        self.prepare(BufferAccess::Update);
+        unsafe {
+            gl::NamedBufferSubData(
+                self.id,
+                (offset * T::STRIDE) as GLintptr,
+                bytes.len() as GLsizeiptr,
+                bytes.as_ptr() as *const GLvoid,
+            );
+        }
+    }
+
+    /// Copies the elements in `range` back to the host.
+    pub fn read(&self, range: Range<usize>) -> Vec<T> {
+        assert!(
+            range.start <= range.end && range.end <= self.len,
+            "read of {:?} is out of bounds for a buffer of {}",
+            range,
+            self.len
+        );
+        let len = range.end - range.start;
+        if len == 0 {
+            return Vec::new();
+        }
+
+        let mut bytes = vec![0; len * T::STRIDE];
+        self.prepare(BufferAccess::Update);
+        unsafe {
+            let ptr = gl::MapNamedBufferRange(
+                self.id,
+                (range.start * T::STRIDE) as GLintptr,
+                bytes.len() as GLsizeiptr,
+                gl::MAP_READ_BIT,
+            ) as *const u8;
+            assert!(!ptr.is_null(), "failed to map buffer {}", self.id);
+
+            // The mapping is only valid until it is unmapped, so copy first
+            std::ptr::copy_nonoverlapping(ptr, bytes.as_mut_ptr(), bytes.len());
+
+            gl::UnmapNamedBuffer(self.id);
+        }
+        bytes
+            .chunks(T::STRIDE)
+            .map(|element| T::read_std430(&element[..T::SIZE]))
+            .collect()
+    }
+
+    /// Copies the whole buffer back to the host.
+    pub fn to_vec(&self) -> Vec<T> {
+        self.read(0..self.len)
+    }
+}
+
+fn to_std430_bytes<T: Std430>(data: &[T]) -> Vec<u8> {
+    let mut bytes = vec![0; data.len() * T::STRIDE];
+    for (element, chunk) in data.iter().zip(bytes.chunks_mut(T::STRIDE)) {
+        element.write_std430(&mut chunk[..T::SIZE]);
+    }
+    bytes
+}
+
+impl<T: Std430> Drop for Buffer<T> {
+    fn drop(&mut self) {
+        unsafe { gl::DeleteBuffers(1, &self.id) };
+        // The id can be reused by the next buffer
+        barrier::delete(self.id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Buffer;
+    use crate::std430::Vec3;
+    use crate::Context;
+
+    #[test]
+    fn test_buffer_round_trip() {
+        let _context = Context::new().unwrap();
+
+        let data: Vec<u32> = (0..1000).collect();
+        let buffer = Buffer::from_slice(&data);
+        assert_eq!(buffer.len(), 1000);
+        assert_eq!(buffer.to_vec(), data);
+        assert_eq!(buffer.read(10..20), &data[10..20]);
+        assert_eq!(buffer.read(5..5), Vec::<u32>::new());
+    }
+
+    #[test]
+    fn test_buffer_new_is_zeroed_and_writable() {
+        let _context = Context::new().unwrap();
+
+        let mut buffer = Buffer::<Vec3>::new(8);
+        assert_eq!(buffer.to_vec(), vec![Vec3([0.0; 3]); 8]);
+
+        buffer.write(6, &[Vec3([1.0, 2.0, 3.0]), Vec3([4.0, 5.0, 6.0])]);
+        assert_eq!(buffer.read(5..8)[0], Vec3([0.0; 3]));
+        assert_eq!(buffer.read(5..8)[2], Vec3([4.0, 5.0, 6.0]));
+    }
+
+    #[test]
+    #[should_panic(expected = "out of bounds")]
+    fn test_buffer_read_out_of_bounds() {
+        let _context = Context::new().unwrap();
+
+        Buffer::<u32>::new(4).read(2..5);
+    }
+}
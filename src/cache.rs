@@ -0,0 +1,212 @@
+use gl::types::*;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::ffi::CStr;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::error::ShaderError;
+use crate::program::Program;
+use crate::template::{Param, ShaderTemplate};
+
+/// Caches linked compute programs on disk as program binaries, so instantiating
+/// the same template with the same parameters on the same driver only compiles
+/// it once.
+///
+/// Binaries the driver does not accept anymore, e.g. after an update it did
+/// not report in its version string, are recompiled and overwritten.
+pub struct ProgramCache {
+    dir: PathBuf,
+}
+
+impl ProgramCache {
+    /// Stores the binaries in `dir`, which is created when first needed.
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        ProgramCache { dir: dir.into() }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Instantiates `template` with `substs` and links it as a compute program,
+    /// or loads it from the cache.
+    pub fn compute_program(
+        &self,
+        template: &ShaderTemplate,
+        substs: &HashMap<&str, Param>,
+    ) -> Result<Program, ShaderError> {
+        let source = template.instantiate(substs)?;
+        let path = self
+            .dir
+            .join(format!("{:016x}.bin", cache_key(&source, substs)));
+
+        if let Ok(bytes) = fs::read(&path) {
+            if bytes.len() > 4 {
+                let format = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+                if let Ok(program) = Program::from_binary(format, &bytes[4..]) {
+                    return Ok(program);
+                }
+            }
+        }
+
+        let kernel = template.compile(substs, gl::COMPUTE_SHADER)?;
+        let program = Program::new(vec![(kernel, gl::COMPUTE_SHADER)])?;
+        if let Some((format, binary)) = program.binary() {
+            let mut bytes = format.to_le_bytes().to_vec();
+            bytes.extend_from_slice(&binary);
+            // The cache is only an optimization, so failing to write it is fine
+            let _ = self.store(&path, &bytes);
+        }
+        Ok(program)
+    }
+
+    fn store(&self, path: &Path, bytes: &[u8]) -> std::io::Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        // Write then rename, so that concurrent readers never see half a file.
+        // Every write gets its own temporary file, even from the same process
+        static WRITES: AtomicUsize = AtomicUsize::new(0);
+        let write = WRITES.fetch_add(1, Ordering::Relaxed);
+        let tmp_path = path.with_extension(format!("{}.{}.tmp", std::process::id(), write));
+        fs::write(&tmp_path, bytes)?;
+        fs::rename(&tmp_path, path)
+    }
+}
+
+/// Hashes everything the program binary depends on. `DefaultHasher` is not
+/// guaranteed to be stable between Rust releases, which at worst causes a
+/// recompile.
+fn cache_key(source: &str, substs: &HashMap<&str, Param>) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    source.hash(&mut hasher);
+    for name in [gl::VENDOR, gl::RENDERER, gl::VERSION] {
+        gl_string(name).hash(&mut hasher);
+    }
+
+    let mut params: Vec<(&str, String)> = substs
+        .iter()
+        .map(|(name, param)| (*name, format!("{:?}", param)))
+        .collect();
+    params.sort();
+    params.hash(&mut hasher);
+
+    hasher.finish()
+}
+
+pub(crate) fn gl_string(name: GLenum) -> String {
+    unsafe {
+        let ptr = gl::GetString(name);
+        if ptr.is_null() {
+            return String::new();
+        }
+        CStr::from_ptr(ptr as *const _)
+            .to_string_lossy()
+            .into_owned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ProgramCache;
+    use crate::template::{Param, ShaderTemplate};
+    use crate::{Buffer, Context};
+    use std::collections::HashMap;
+    use std::fs;
+
+    const SOURCE: &str = "
+#version 450 core
+#define N
+#define VALUE
+layout(local_size_x = N, local_size_y = 1, local_size_z = 1) in;
+
+layout(std430, binding = 0) buffer Data {
+    uint data[N];
+};
+
+void main() {
+    data[gl_LocalInvocationID.x] = VALUE;
+}
+";
+
+    fn run(cache: &ProgramCache, template: &ShaderTemplate, value: u32) -> Vec<u32> {
+        let mut substs: HashMap<&str, Param> = HashMap::new();
+        substs.insert("N", 4usize.into());
+        substs.insert("VALUE", value.into());
+        let program = cache.compute_program(template, &substs).unwrap();
+
+        let buffer = Buffer::<u32>::new(4);
+        buffer.bind(0);
+        program.dispatch(1, 1, 1);
+        buffer.to_vec()
+    }
+
+    fn cached_files(cache: &ProgramCache) -> Vec<std::path::PathBuf> {
+        let mut files: Vec<_> = fs::read_dir(cache.dir())
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect();
+        files.sort();
+        files
+    }
+
+    #[test]
+    fn test_program_cache() {
+        let _context = Context::new().unwrap();
+        let dir =
+            std::env::temp_dir().join(format!("compute-shader-cache-test-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let cache = ProgramCache::new(&dir);
+        let template = ShaderTemplate::parse("cache.comp.glsl", SOURCE).unwrap();
+
+        // A miss compiles and stores the binary, a hit loads it
+        assert_eq!(run(&cache, &template, 7), vec![7; 4]);
+        assert_eq!(cached_files(&cache).len(), 1);
+        assert_eq!(run(&cache, &template, 7), vec![7; 4]);
+        assert_eq!(cached_files(&cache).len(), 1);
+
+        // Different parameters are a different program
+        assert_eq!(run(&cache, &template, 9), vec![9; 4]);
+        let files = cached_files(&cache);
+        assert_eq!(files.len(), 2);
+
+        // A binary the driver rejects is recompiled and replaced
+        for file in &files {
+            fs::write(file, b"\x01\x00\x00\x00not a program binary").unwrap();
+        }
+        assert_eq!(run(&cache, &template, 7), vec![7; 4]);
+        assert_eq!(cached_files(&cache), files);
+        assert!(files.iter().any(|file| fs::read(file).unwrap().len() > 24));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_program_cache_concurrent_stores() {
+        let dir = std::env::temp_dir().join(format!(
+            "compute-shader-cache-store-test-{}",
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        let cache = ProgramCache::new(&dir);
+        let path = dir.join("0000000000000000.bin");
+
+        // Threads storing the same binary must not clobber each other's
+        // temporary files
+        let bytes = vec![42u8; 1 << 16];
+        std::thread::scope(|scope| {
+            for _ in 0..8 {
+                scope.spawn(|| {
+                    for _ in 0..10 {
+                        cache.store(&path, &bytes).unwrap();
+                    }
+                });
+            }
+        });
+        assert_eq!(cached_files(&cache), vec![path.clone()]);
+        assert_eq!(fs::read(&path).unwrap(), bytes);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,504 @@
+use gl::types::*;
+use std::collections::HashMap;
+use std::marker::PhantomData;
+
+use crate::element::{check_supported, Element};
+use crate::error::ShaderError;
+use crate::scan::{kernel, Scan, ScanKind, ScanOp};
+use crate::std430::UVec3;
+use crate::template::Param;
+use crate::{Buffer, Program, Std430};
+
+/// The number of elements a compaction kept, written on the GPU, followed by
+/// the parameters of an indirect dispatch over them, see `Compacted::dispatch`.
+#[derive(Debug, Copy, Clone, PartialEq, Std430)]
+pub struct CompactCounter {
+    pub len: GLuint,
+    /// One work group per `Compact::dispatch_group_size` kept elements.
+    pub num_groups: UVec3,
+}
+
+/// The output of a compaction, which stays on the GPU until read.
+pub struct Compacted<T: Std430> {
+    /// As long as the input, starting with the kept elements in their original
+    /// order. The rest is zeroed, or holds the other elements in their original
+    /// order after `Compact::partition`.
+    pub data: Buffer<T>,
+    pub counter: Buffer<CompactCounter>,
+}
+
+impl<T: Std430> Compacted<T> {
+    /// Reads back the number of kept elements.
+    pub fn len(&self) -> usize {
+        self.counter.to_vec()[0].len as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Reads back the kept elements.
+    pub fn to_vec(&self) -> Vec<T> {
+        self.data.read(0..self.len())
+    }
+
+    /// Dispatches `program` with one work group per
+    /// `Compact::dispatch_group_size` kept elements, without reading the count
+    /// back.
+    pub fn dispatch(&self, program: &Program) {
+        let offset = CompactCounter::FIELDS
+            .iter()
+            .find(|(name, _)| *name == "num_groups")
+            .unwrap()
+            .1;
+        program.dispatch_indirect(&self.counter, offset);
+    }
+}
+
+/// Stream compaction: keeps the elements of a buffer that match a predicate,
+/// in order. The elements are flagged and counted in `compaction_flags`, the
+/// flags are scanned with `Scan` to find the offset of each kept element, and
+/// the kept elements are moved there in `compaction_scatter`.
+pub struct Compact<T: Element> {
+    functions: String,
+    predicate: String,
+    dispatch_group_size: GLuint,
+    flag: Program,
+    scan: Scan<GLuint>,
+    scatter: Program,
+    scatter_payload: Program,
+    fill_dispatch: Program,
+    _marker: PhantomData<T>,
+}
+
+impl<T: Element> Compact<T> {
+    /// `predicate` is a GLSL boolean expression of the element `x` and its
+    /// index `i`, e.g. `x % 2 == 0` or `i < 10 && x > 0.5`.
+    pub fn new(predicate: &str) -> Result<Self, ShaderError> {
+        Self::with_functions("", predicate)
+    }
+
+    /// Like `new`, with GLSL function definitions the predicate can call, for
+    /// predicates that need statements, e.g. loops.
+    pub fn with_functions(functions: &str, predicate: &str) -> Result<Self, ShaderError> {
+        check_supported::<T>()?;
+
+        let no_substs: HashMap<&str, Param> = HashMap::new();
+        let mut substs: HashMap<&str, Param> = HashMap::new();
+        substs.insert("TYPE", T::GLSL_TYPE.into());
+        let scatter = kernel(
+            "compaction/compaction_scatter.comp.glsl",
+            include_str!("../shaders/compaction/compaction_scatter.comp.glsl"),
+            &substs,
+        )?;
+        substs.insert("FUNCTIONS", functions.into());
+        substs.insert("PREDICATE", predicate.into());
+        let flag = kernel(
+            "compaction/compaction_flags.comp.glsl",
+            include_str!("../shaders/compaction/compaction_flags.comp.glsl"),
+            &substs,
+        )?;
+
+        Ok(Compact {
+            functions: functions.to_string(),
+            predicate: predicate.to_string(),
+            dispatch_group_size: 256,
+            flag,
+            scan: Scan::new(ScanOp::Add)?,
+            scatter,
+            scatter_payload: kernel(
+                "compaction/compaction_scatter_payload.comp.glsl",
+                include_str!("../shaders/compaction/compaction_scatter_payload.comp.glsl"),
+                &no_substs,
+            )?,
+            fill_dispatch: kernel(
+                "compaction/compaction_dispatch.comp.glsl",
+                include_str!("../shaders/compaction/compaction_dispatch.comp.glsl"),
+                &no_substs,
+            )?,
+            _marker: PhantomData,
+        })
+    }
+
+    pub fn functions(&self) -> &str {
+        &self.functions
+    }
+
+    pub fn predicate(&self) -> &str {
+        &self.predicate
+    }
+
+    pub fn dispatch_group_size(&self) -> GLuint {
+        self.dispatch_group_size
+    }
+
+    /// Sets the work group size of the kernels that `Compacted::dispatch` will
+    /// run over the kept elements, 256 by default.
+    pub fn set_dispatch_group_size(&mut self, size: GLuint) {
+        assert!(size > 0, "the work group size cannot be 0");
+        self.dispatch_group_size = size;
+    }
+
+    /// Keeps the elements of `input` matching the predicate.
+    pub fn run(&self, input: &Buffer<T>) -> Compacted<T> {
+        let flags = Buffer::<GLuint>::new(input.len());
+        let (compacted, _) = self.flag_and_scan(input, &flags, false);
+        compacted
+    }
+
+    /// Keeps the keys in `keys` matching the predicate, and the elements of
+    /// `payload` at the same indices, in the same order.
+    pub fn run_with_payload<P: Std430>(
+        &self,
+        keys: &Buffer<T>,
+        payload: &Buffer<P>,
+    ) -> (Compacted<T>, Buffer<P>) {
+        self.scatter_with_payload(keys, payload, false)
+    }
+
+    /// Moves the elements of `input` matching the predicate first and the
+    /// others after them, both in their original order. The split point is
+    /// `Compacted::len`.
+    pub fn partition(&self, input: &Buffer<T>) -> Compacted<T> {
+        let flags = Buffer::<GLuint>::new(input.len());
+        let (partitioned, _) = self.flag_and_scan(input, &flags, true);
+        partitioned
+    }
+
+    /// Like `partition`, moving the elements of `payload` along with their
+    /// keys.
+    pub fn partition_with_payload<P: Std430>(
+        &self,
+        keys: &Buffer<T>,
+        payload: &Buffer<P>,
+    ) -> (Compacted<T>, Buffer<P>) {
+        self.scatter_with_payload(keys, payload, true)
+    }
+
+    fn scatter_with_payload<P: Std430>(
+        &self,
+        keys: &Buffer<T>,
+        payload: &Buffer<P>,
+        partition: bool,
+    ) -> (Compacted<T>, Buffer<P>) {
+        assert_eq!(
+            keys.len(),
+            payload.len(),
+            "there must be one payload element per key"
+        );
+
+        let flags = Buffer::<GLuint>::new(keys.len());
+        let (compacted, offsets) = self.flag_and_scan(keys, &flags, partition);
+        let payload_output = Buffer::<P>::new(payload.len());
+        if let Some(offsets) = offsets {
+            // Strides are multiples of the alignment, which is at least 4
+            let program = &self.scatter_payload;
+            unsafe {
+                gl::ProgramUniform1ui(program.get_id(), 0, keys.len() as GLuint);
+                gl::ProgramUniform1ui(program.get_id(), 1, (P::STRIDE / 4) as GLuint);
+                gl::ProgramUniform1ui(program.get_id(), 2, partition as GLuint);
+            }
+            flags.bind(1);
+            offsets.bind(2);
+            compacted.counter.bind(3);
+            payload.bind(4);
+            payload_output.bind(5);
+            program.dispatch(self.work_groups(keys.len()), 1, 1);
+        }
+        (compacted, payload_output)
+    }
+
+    /// Compacts or partitions `input` and returns the scanned flags, if there
+    /// are any elements.
+    pub(crate) fn flag_and_scan(
+        &self,
+        input: &Buffer<T>,
+        flags: &Buffer<GLuint>,
+        partition: bool,
+    ) -> (Compacted<T>, Option<Buffer<GLuint>>) {
+        let compacted = Compacted {
+            data: Buffer::new(input.len()),
+            counter: Buffer::new(1),
+        };
+
+        let mut offsets = None;
+        if !input.is_empty() {
+            for program in [&self.flag, &self.scatter] {
+                unsafe { gl::ProgramUniform1ui(program.get_id(), 0, input.len() as GLuint) };
+            }
+            input.bind(0);
+            flags.bind(1);
+            compacted.counter.bind(2);
+            self.flag.dispatch(self.work_groups(input.len()), 1, 1);
+
+            let scanned = self.scan.run(flags, ScanKind::Exclusive);
+
+            unsafe { gl::ProgramUniform1ui(self.scatter.get_id(), 1, partition as GLuint) };
+            input.bind(0);
+            flags.bind(1);
+            scanned.bind(2);
+            compacted.data.bind(3);
+            compacted.counter.bind(4);
+            self.scatter.dispatch(self.work_groups(input.len()), 1, 1);
+            offsets = Some(scanned);
+        }
+
+        unsafe {
+            let id = self.fill_dispatch.get_id();
+            gl::ProgramUniform1ui(id, 0, self.dispatch_group_size);
+        }
+        compacted.counter.bind(2);
+        self.fill_dispatch.dispatch(1, 1, 1);
+
+        (compacted, offsets)
+    }
+
+    fn work_groups(&self, len: usize) -> GLuint {
+        len.div_ceil(self.flag.work_group_size()[0] as usize) as GLuint
+    }
+}
+
+/// Compacts `input` with a one-off `Compact`, see `Compact::new` for the
+/// predicate. Keep the `Compact` around to compact many buffers, it compiles
+/// its kernels once.
+pub fn compact<T: Element>(
+    input: &Buffer<T>,
+    predicate: &str,
+) -> Result<Compacted<T>, ShaderError> {
+    Ok(Compact::new(predicate)?.run(input))
+}
+
+/// Partitions `input` with a one-off `Compact`, see `Compact::partition`.
+pub fn partition<T: Element>(
+    input: &Buffer<T>,
+    predicate: &str,
+) -> Result<Compacted<T>, ShaderError> {
+    Ok(Compact::new(predicate)?.partition(input))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{compact, partition, Compact, CompactCounter};
+    use crate::std430::{UVec3, Vec2};
+    use crate::template::{Param, ShaderTemplate};
+    use crate::{Buffer, Context, Program, ShaderError, Std430};
+    use rand::Rng;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_compact() {
+        let _context = Context::new().unwrap();
+        let mut rng = rand::thread_rng();
+        let even = Compact::<u32>::new("x % 2 == 0").unwrap();
+
+        for &len in &[0, 1, 2, 255, 256, 257, 10_000, 300_000] {
+            let input: Vec<u32> = (0..len).map(|_| rng.gen()).collect();
+            let compacted = even.run(&Buffer::from_slice(&input));
+
+            let expected: Vec<u32> = input.iter().copied().filter(|x| x % 2 == 0).collect();
+            assert_eq!(compacted.data.len(), len);
+            assert_eq!(compacted.len(), expected.len());
+            assert_eq!(compacted.to_vec(), expected, "len {}", len);
+        }
+    }
+
+    #[test]
+    fn test_compact_index_and_types() {
+        let _context = Context::new().unwrap();
+        let input: Vec<Vec2> = (0..1000).map(|i| Vec2([i as f32, -(i as f32)])).collect();
+
+        // Every third element, as long as it is not too large
+        let compacted = compact(&Buffer::from_slice(&input), "i % 3 == 0 && x.x < 500.0").unwrap();
+
+        let expected: Vec<Vec2> = input
+            .iter()
+            .copied()
+            .enumerate()
+            .filter(|&(i, x)| i % 3 == 0 && x.0[0] < 500.0)
+            .map(|(_, x)| x)
+            .collect();
+        assert_eq!(compacted.to_vec(), expected);
+    }
+
+    #[test]
+    fn test_compact_helper_function() {
+        let _context = Context::new().unwrap();
+
+        // The primes, which need a loop to find
+        let primes = Compact::<u32>::with_functions(
+            "
+            bool is_prime(uint n) {
+                if (n < 2) {
+                    return false;
+                }
+                for (uint d = 2; d * d <= n; d++) {
+                    if (n % d == 0) {
+                        return false;
+                    }
+                }
+                return true;
+            }",
+            "is_prime(x)",
+        )
+        .unwrap();
+        let input: Vec<u32> = (0..10_000).collect();
+        let compacted = primes.run(&Buffer::from_slice(&input));
+
+        let is_prime = |n: u32| {
+            n >= 2
+                && (2..n)
+                    .take_while(|d| d * d <= n)
+                    .all(|d| !n.is_multiple_of(d))
+        };
+        let expected: Vec<u32> = input.into_iter().filter(|&n| is_prime(n)).collect();
+        assert_eq!(expected.len(), 1229);
+        assert_eq!(compacted.to_vec(), expected);
+    }
+
+    #[test]
+    fn test_compact_payload() {
+        #[derive(Debug, Copy, Clone, PartialEq, Std430)]
+        struct Hit {
+            voxel: UVec3,
+            distance: f32,
+            normal: [f32; 3],
+        }
+
+        let _context = Context::new().unwrap();
+        let mut rng = rand::thread_rng();
+        let keys: Vec<u32> = (0..5000).map(|_| rng.gen_range(0, 2)).collect();
+        let hits: Vec<Hit> = (0..5000)
+            .map(|i| Hit {
+                voxel: UVec3([i, rng.gen(), rng.gen()]),
+                distance: rng.gen(),
+                normal: [rng.gen(), rng.gen(), rng.gen()],
+            })
+            .collect();
+
+        let has_hit = Compact::<u32>::new("x != 0").unwrap();
+        let (compacted, payload) =
+            has_hit.run_with_payload(&Buffer::from_slice(&keys), &Buffer::from_slice(&hits));
+
+        // Stable: the hits stay in the order of their voxels
+        let expected: Vec<Hit> = keys
+            .iter()
+            .zip(&hits)
+            .filter(|(key, _)| **key != 0)
+            .map(|(_, hit)| *hit)
+            .collect();
+        assert_eq!(compacted.len(), expected.len());
+        assert_eq!(payload.read(0..compacted.len()), expected);
+    }
+
+    const DOUBLE: &str = "
+#version 450 core
+layout(local_size_x = 64, local_size_y = 1, local_size_z = 1) in;
+
+layout(std430, binding = 0) buffer Data {
+    uint data[];
+};
+
+void main() {
+    data[gl_GlobalInvocationID.x] *= 2;
+}
+";
+
+    #[test]
+    fn test_compact_counter_indirect_dispatch() {
+        let _context = Context::new().unwrap();
+        let template = ShaderTemplate::parse("double.comp.glsl", DOUBLE).unwrap();
+        let kernel = template
+            .compile(&HashMap::<&str, Param>::new(), gl::COMPUTE_SHADER)
+            .unwrap();
+        let double = Program::new(vec![(kernel, gl::COMPUTE_SHADER)]).unwrap();
+
+        let mut below_100 = Compact::<u32>::new("x < 100").unwrap();
+        below_100.set_dispatch_group_size(64);
+        let input: Vec<u32> = (0..1000).map(|i| (i * 7) % 1000).collect();
+        let compacted = below_100.run(&Buffer::from_slice(&input));
+
+        // 100 elements kept, in 2 work groups of 64
+        assert_eq!(
+            compacted.counter.to_vec()[0],
+            CompactCounter {
+                len: 100,
+                num_groups: UVec3([2, 1, 1]),
+            }
+        );
+
+        // Doubles the kept elements and the 28 after them, without the count
+        // going through the host
+        compacted.data.bind(0);
+        compacted.dispatch(&double);
+        let expected: Vec<u32> = input.iter().filter(|&&x| x < 100).map(|x| x * 2).collect();
+        let output = compacted.data.read(0..128);
+        assert_eq!(output[..100], expected[..]);
+        assert_eq!(output[100..], [0; 28]);
+    }
+
+    #[test]
+    fn test_partition() {
+        let _context = Context::new().unwrap();
+        let mut rng = rand::thread_rng();
+        let below_half = Compact::<f32>::new("x < 0.5").unwrap();
+
+        for &len in &[0, 1, 255, 257, 10_000, 300_000] {
+            let input: Vec<f32> = (0..len).map(|_| rng.gen()).collect();
+            let partitioned = below_half.partition(&Buffer::from_slice(&input));
+
+            let (mut expected, rest): (Vec<f32>, Vec<f32>) = input.iter().partition(|&&x| x < 0.5);
+            assert_eq!(partitioned.len(), expected.len(), "len {}", len);
+            expected.extend(rest);
+            assert_eq!(partitioned.data.to_vec(), expected, "len {}", len);
+        }
+
+        let all = partition(&Buffer::from_slice(&[1u32, 2, 3]), "true").unwrap();
+        assert_eq!((all.len(), all.data.to_vec()), (3, vec![1, 2, 3]));
+        let none = partition(&Buffer::from_slice(&[1u32, 2, 3]), "false").unwrap();
+        assert_eq!((none.len(), none.data.to_vec()), (0, vec![1, 2, 3]));
+    }
+
+    #[test]
+    fn test_partition_payload() {
+        let _context = Context::new().unwrap();
+        let mut rng = rand::thread_rng();
+
+        // Rays split into a hit queue and a miss queue, by their hit distance
+        let distances: Vec<f32> = (0..5000)
+            .map(|_| {
+                if rng.gen() {
+                    rng.gen_range(0.0, 100.0)
+                } else {
+                    -1.0
+                }
+            })
+            .collect();
+        let rays: Vec<Vec2> = (0..5000).map(|i| Vec2([i as f32, rng.gen()])).collect();
+        let hit = Compact::<f32>::new("x >= 0.0").unwrap();
+        let (queues, ray_queues) =
+            hit.partition_with_payload(&Buffer::from_slice(&distances), &Buffer::from_slice(&rays));
+
+        let (hits, misses): (Vec<_>, Vec<_>) = distances
+            .into_iter()
+            .zip(rays)
+            .partition(|&(distance, _)| distance >= 0.0);
+        let split = queues.len();
+        assert_eq!(split, hits.len());
+        let expected: Vec<Vec2> = hits.iter().chain(&misses).map(|&(_, ray)| ray).collect();
+        assert_eq!(ray_queues.to_vec(), expected);
+        assert_eq!(queues.data.read(split..5000), vec![-1.0; misses.len()]);
+    }
+
+    #[test]
+    fn test_compact_predicate_error() {
+        let _context = Context::new().unwrap();
+
+        match Compact::<f32>::new("x % 2 == 0") {
+            Err(ShaderError::Compile { shader, .. }) => {
+                assert_eq!(shader, "compaction/compaction_flags.comp.glsl")
+            }
+            Err(err) => panic!("unexpected error: {}", err),
+            Ok(_) => panic!("`%` is not defined for floats"),
+        }
+    }
+}
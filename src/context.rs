@@ -0,0 +1,201 @@
+use gl::types::*;
+
+use crate::debug_message_callback::{self, DebugSink};
+
+#[cfg(not(any(feature = "glfw", feature = "egl")))]
+compile_error!("at least one of the `glfw` or `egl` features must be enabled");
+
+/// An OpenGL 4.5+ context, current on the thread that created it, with the
+/// `gl` function pointers loaded and the debug message callback installed.
+///
+/// Nothing is ever drawn: the context only exists to run compute shaders, so
+/// GLFW gets a hidden window and EGL gets no surface at all.
+pub struct Context {
+    backend: Backend,
+    // Boxed so that the pointer given to the driver survives moving the
+    // context, and after the backend so that it outlives the GL context
+    debug_sink: Box<DebugSink>,
+}
+
+enum Backend {
+    #[cfg(feature = "glfw")]
+    Glfw {
+        _glfw: glfw::Glfw,
+        _window: glfw::Window,
+    },
+    #[cfg(feature = "egl")]
+    Egl {
+        egl: khronos_egl::Instance<khronos_egl::Static>,
+        display: khronos_egl::Display,
+        context: khronos_egl::Context,
+    },
+}
+
+// EGL_PLATFORM_SURFACELESS_MESA, from EGL_MESA_platform_surfaceless
+#[cfg(feature = "egl")]
+const PLATFORM_SURFACELESS_MESA: khronos_egl::Enum = 0x31DD;
+
+impl Context {
+    /// Creates a context with the first backend that works: a hidden GLFW
+    /// window if the `glfw` feature is enabled, otherwise (or if there is no
+    /// display to open it on) a surfaceless EGL context.
+    pub fn new() -> Result<Context, String> {
+        #[cfg(all(feature = "glfw", feature = "egl"))]
+        return Context::with_glfw().or_else(|glfw_err| {
+            Context::surfaceless().map_err(|egl_err| format!("{}; {}", glfw_err, egl_err))
+        });
+
+        #[cfg(all(feature = "glfw", not(feature = "egl")))]
+        return Context::with_glfw();
+
+        #[cfg(all(feature = "egl", not(feature = "glfw")))]
+        return Context::surfaceless();
+    }
+
+    /// Creates a hidden GLFW window and makes its context current.
+    #[cfg(feature = "glfw")]
+    pub fn with_glfw() -> Result<Context, String> {
+        use glfw::Context as _;
+
+        let mut glfw = glfw::init::<()>(None).map_err(|err| format!("GLFW: {}", err))?;
+        glfw.window_hint(glfw::WindowHint::Visible(false));
+        glfw.window_hint(glfw::WindowHint::ContextVersion(4, 5));
+        glfw.window_hint(glfw::WindowHint::OpenGlForwardCompat(true));
+        glfw.window_hint(glfw::WindowHint::OpenGlDebugContext(true));
+
+        let (mut window, _) = glfw
+            .create_window(300, 300, "Hello this is window", glfw::WindowMode::Windowed)
+            .ok_or_else(|| "GLFW: failed to create window".to_string())?;
+        window.make_current();
+        gl::load_with(|s| window.get_proc_address(s));
+
+        let context = Context {
+            backend: Backend::Glfw {
+                _glfw: glfw,
+                _window: window,
+            },
+            debug_sink: Box::new(DebugSink::Stdout),
+        };
+        context.install_debug_message_callback();
+        Ok(context)
+    }
+
+    /// Creates an EGL context on Mesa's surfaceless platform, which needs
+    /// neither a window system nor a GPU (llvmpipe works fine), and makes it
+    /// current without any surface.
+    #[cfg(feature = "egl")]
+    pub fn surfaceless() -> Result<Context, String> {
+        let egl = khronos_egl::Instance::new(khronos_egl::Static);
+        let egl_err = |what: &str, err: khronos_egl::Error| format!("EGL: {}: {}", what, err);
+
+        let display = egl
+            .get_platform_display(
+                PLATFORM_SURFACELESS_MESA,
+                std::ptr::null_mut(),
+                &[khronos_egl::ATTRIB_NONE],
+            )
+            .map_err(|err| egl_err("failed to get the surfaceless display", err))?;
+        egl.initialize(display)
+            .map_err(|err| egl_err("failed to initialize the display", err))?;
+        egl.bind_api(khronos_egl::OPENGL_API)
+            .map_err(|err| egl_err("failed to bind the OpenGL API", err))?;
+
+        let config = egl
+            .choose_first_config(
+                display,
+                &[
+                    khronos_egl::RENDERABLE_TYPE,
+                    khronos_egl::OPENGL_BIT,
+                    khronos_egl::SURFACE_TYPE,
+                    0,
+                    khronos_egl::NONE,
+                ],
+            )
+            .map_err(|err| egl_err("failed to choose a config", err))?
+            .ok_or_else(|| "EGL: no config supports OpenGL".to_string())?;
+
+        let context = egl
+            .create_context(
+                display,
+                config,
+                None,
+                &[
+                    khronos_egl::CONTEXT_MAJOR_VERSION,
+                    4,
+                    khronos_egl::CONTEXT_MINOR_VERSION,
+                    5,
+                    khronos_egl::CONTEXT_OPENGL_PROFILE_MASK,
+                    khronos_egl::CONTEXT_OPENGL_CORE_PROFILE_BIT,
+                    khronos_egl::CONTEXT_OPENGL_DEBUG,
+                    khronos_egl::TRUE as khronos_egl::Int,
+                    khronos_egl::NONE,
+                ],
+            )
+            .map_err(|err| egl_err("failed to create an OpenGL 4.5 context", err))?;
+        if let Err(err) = egl.make_current(display, None, None, Some(context)) {
+            let _ = egl.destroy_context(display, context);
+            return Err(egl_err("failed to make the context current", err));
+        }
+        gl::load_with(|s| {
+            egl.get_proc_address(s)
+                .map_or(std::ptr::null(), |f| f as *const std::ffi::c_void)
+        });
+
+        let context = Context {
+            backend: Backend::Egl {
+                egl,
+                display,
+                context,
+            },
+            debug_sink: Box::new(DebugSink::Stdout),
+        };
+        context.install_debug_message_callback();
+        Ok(context)
+    }
+
+    /// Sends the driver's debug messages to `sink` from now on. They are
+    /// delivered synchronously, from inside the GL call that raised them.
+    pub fn set_debug_sink(&mut self, sink: DebugSink) {
+        let old_sink = std::mem::replace(&mut self.debug_sink, Box::new(sink));
+        self.install_debug_message_callback();
+        // Only dropped once the driver has the pointer to the new one
+        drop(old_sink);
+    }
+
+    /// Panics with the first message a `DebugSink::PanicAt` recorded since the
+    /// last check. `Program::dispatch` checks after every dispatch.
+    pub fn check(&self) {
+        debug_message_callback::check();
+    }
+
+    fn install_debug_message_callback(&self) {
+        unsafe {
+            gl::Enable(gl::DEBUG_OUTPUT_SYNCHRONOUS);
+            gl::DebugMessageCallback(
+                Some(debug_message_callback::callback),
+                &*self.debug_sink as *const DebugSink as *const GLvoid,
+            )
+        }
+    }
+}
+
+impl Drop for Context {
+    fn drop(&mut self) {
+        match &self.backend {
+            // Dropping the window destroys its context
+            #[cfg(feature = "glfw")]
+            Backend::Glfw { .. } => (),
+            // The display is shared by every context on this platform, so it
+            // must not be terminated here
+            #[cfg(feature = "egl")]
+            Backend::Egl {
+                egl,
+                display,
+                context,
+            } => {
+                let _ = egl.make_current(*display, None, None, None);
+                let _ = egl.destroy_context(*display, *context);
+            }
+        }
+    }
+}
@@ -1,6 +1,152 @@
 use gl::types::*;
+use std::cell::RefCell;
 use std::ffi::CStr;
+use std::fmt;
+use std::sync::{Arc, Mutex};
 
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum DebugSeverity {
+    Notification,
+    Low,
+    Medium,
+    High,
+}
+
+/// A message from the driver's `GL_KHR_debug` output.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DebugMessage {
+    pub source: GLenum,
+    pub type_: GLenum,
+    pub id: GLuint,
+    pub severity: DebugSeverity,
+    pub message: String,
+}
+
+/// Where the context sends debug messages, see `Context::set_debug_sink`.
+pub enum DebugSink {
+    /// Prints everything but notifications to stdout. The default.
+    Stdout,
+    /// Logs through the `log` crate, high severity messages as errors,
+    /// medium as warnings, low as info and notifications as debug.
+    Log,
+    /// Appends every message to a shared buffer.
+    Collect(DebugMessages),
+    /// Prints like `Stdout`, and records the first message of at least the
+    /// given severity. A panic cannot unwind through the driver, so it is
+    /// raised once the GL call returns, by the next `Program::dispatch` or
+    /// `Context::check`.
+    PanicAt(DebugSeverity),
+    Custom(Box<dyn Fn(&DebugMessage) + Send + Sync>),
+}
+
+/// A thread-safe buffer of debug messages, cloned into a `DebugSink::Collect`.
+#[derive(Debug, Clone, Default)]
+pub struct DebugMessages(Arc<Mutex<Vec<DebugMessage>>>);
+
+impl DebugMessages {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Removes and returns the messages received so far.
+    pub fn take(&self) -> Vec<DebugMessage> {
+        std::mem::take(&mut *self.0.lock().unwrap())
+    }
+
+    fn push(&self, message: DebugMessage) {
+        self.0.lock().unwrap().push(message);
+    }
+}
+
+thread_local! {
+    // The first message a `DebugSink::PanicAt` recorded on this thread, which
+    // is the thread whose context raised it since the output is synchronous
+    static VIOLATION: RefCell<Option<DebugMessage>> = const { RefCell::new(None) };
+}
+
+/// Panics with the message recorded by a `DebugSink::PanicAt`, if any.
+pub(crate) fn check() {
+    if let Some(message) = VIOLATION.with(|violation| violation.borrow_mut().take()) {
+        panic!("{}", message);
+    }
+}
+
+impl DebugSink {
+    fn handle(&self, message: DebugMessage) {
+        match self {
+            DebugSink::Stdout => {
+                if message.severity != DebugSeverity::Notification {
+                    println!("{}", message);
+                }
+            }
+            DebugSink::Log => {
+                let level = match message.severity {
+                    DebugSeverity::High => log::Level::Error,
+                    DebugSeverity::Medium => log::Level::Warn,
+                    DebugSeverity::Low => log::Level::Info,
+                    DebugSeverity::Notification => log::Level::Debug,
+                };
+                log::log!(level, "{}", message);
+            }
+            DebugSink::Collect(messages) => messages.push(message),
+            DebugSink::PanicAt(threshold) => {
+                if message.severity >= *threshold {
+                    VIOLATION.with(|violation| {
+                        violation
+                            .borrow_mut()
+                            .get_or_insert_with(|| message.clone());
+                    });
+                }
+                DebugSink::Stdout.handle(message);
+            }
+            DebugSink::Custom(handler) => handler(&message),
+        }
+    }
+}
+
+impl fmt::Display for DebugSeverity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DebugSeverity::High => write!(f, "HIGH"),
+            DebugSeverity::Medium => write!(f, "MEDIUM"),
+            DebugSeverity::Low => write!(f, "LOW"),
+            DebugSeverity::Notification => write!(f, "NOTIFICATION"),
+        }
+    }
+}
+
+impl fmt::Display for DebugMessage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let source = match self.source {
+            gl::DEBUG_SOURCE_API => "API",
+            gl::DEBUG_SOURCE_WINDOW_SYSTEM => "WINDOW SYSTEM",
+            gl::DEBUG_SOURCE_SHADER_COMPILER => "SHADER COMPILER",
+            gl::DEBUG_SOURCE_THIRD_PARTY => "THIRD PARTY",
+            gl::DEBUG_SOURCE_APPLICATION => "APPLICATION",
+            _ => "UNKNOWN",
+        };
+
+        let type_ = match self.type_ {
+            gl::DEBUG_TYPE_ERROR => "ERROR",
+            gl::DEBUG_TYPE_DEPRECATED_BEHAVIOR => "DEPRECATED BEHAVIOR",
+            gl::DEBUG_TYPE_UNDEFINED_BEHAVIOR => "UNDEFINED BEHAVIOR",
+            gl::DEBUG_TYPE_PORTABILITY => "PORTABILITY",
+            gl::DEBUG_TYPE_PERFORMANCE => "PERFORMANCE",
+            gl::DEBUG_TYPE_OTHER => "OTHER",
+            gl::DEBUG_TYPE_MARKER => "MARKER",
+            _ => "UNKNOWN",
+        };
+
+        write!(
+            f,
+            "{}: {} of {} severity, raised from {}: {}",
+            self.id, type_, self.severity, source, self.message
+        )
+    }
+}
+
+/// The callback registered with `glDebugMessageCallback`, `user_param` points
+/// to the context's `DebugSink`.
 pub extern "system" fn callback(
     source: GLenum,
     type_: GLenum,
@@ -8,55 +154,151 @@ pub extern "system" fn callback(
     severity: GLenum,
     _length: GLsizei,
     message: *const GLchar,
-    _user_param: *mut GLvoid,
+    user_param: *mut GLvoid,
 ) {
-    let mut _source = "";
-    match source {
-        gl::DEBUG_SOURCE_API => _source = "API",
-        gl::DEBUG_SOURCE_WINDOW_SYSTEM => _source = "WINDOW SYSTEM",
-        gl::DEBUG_SOURCE_SHADER_COMPILER => _source = "SHADER COMPILER",
-        gl::DEBUG_SOURCE_THIRD_PARTY => _source = "THIRD PARTY",
-        gl::DEBUG_SOURCE_APPLICATION => _source = "APPLICATION",
-        gl::DEBUG_SOURCE_OTHER => _source = "UNKNOWN",
-        _ => _source = "UNKNOWN",
-    }
-
-    let mut _type = "";
-    match type_ {
-        gl::DEBUG_TYPE_ERROR => _type = "ERROR",
-        gl::DEBUG_TYPE_DEPRECATED_BEHAVIOR => _type = "DEPRECATED BEHAVIOR",
-        gl::DEBUG_TYPE_UNDEFINED_BEHAVIOR => _type = "UDEFINED BEHAVIOR",
-        gl::DEBUG_TYPE_PORTABILITY => _type = "PORTABILITY",
-        gl::DEBUG_TYPE_PERFORMANCE => _type = "PERFORMANCE",
-        gl::DEBUG_TYPE_OTHER => _type = "OTHER",
-        gl::DEBUG_TYPE_MARKER => _type = "MARKER",
-        _ => _type = "UNKNOWN",
-    }
-
-    let mut _severity = "";
-    match severity {
-        gl::DEBUG_SEVERITY_HIGH => _severity = "HIGH",
-        gl::DEBUG_SEVERITY_MEDIUM => _severity = "MEDIUM",
-        gl::DEBUG_SEVERITY_LOW => _severity = "LOW",
-        gl::DEBUG_SEVERITY_NOTIFICATION => _severity = "NOTIFICATION",
-        _ => _severity = "UNKNOWN",
-    }
-
-    if _severity == "NOTIFICATION" {
-        return;
-    }
+    let severity = match severity {
+        gl::DEBUG_SEVERITY_HIGH => DebugSeverity::High,
+        gl::DEBUG_SEVERITY_MEDIUM => DebugSeverity::Medium,
+        gl::DEBUG_SEVERITY_LOW => DebugSeverity::Low,
+        _ => DebugSeverity::Notification,
+    };
 
     let msg = unsafe { CStr::from_ptr(message) };
-    println!(
-        "{}: {} of {} severity, raised from {}: {}",
+    let sink = unsafe { &*(user_param as *const DebugSink) };
+    sink.handle(DebugMessage {
+        source,
+        type_,
         id,
-        _type,
-        _severity,
-        _source,
-        msg.to_owned().to_string_lossy()
-    );
-
-    if !(_severity == "LOW" || _severity == "NOTIFICATION") {
-        // panic!();
+        severity,
+        message: msg.to_string_lossy().into_owned(),
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DebugMessages, DebugSeverity, DebugSink};
+    use crate::Context;
+    use gl::types::*;
+    use std::sync::Mutex;
+
+    fn insert_message(severity: GLenum, message: &str) {
+        let message = std::ffi::CString::new(message).unwrap();
+        unsafe {
+            gl::DebugMessageInsert(
+                gl::DEBUG_SOURCE_APPLICATION,
+                gl::DEBUG_TYPE_OTHER,
+                0,
+                severity,
+                -1,
+                message.as_ptr(),
+            )
+        };
+    }
+
+    #[test]
+    fn test_debug_sink_collect() {
+        let mut context = Context::new().unwrap();
+        let messages = DebugMessages::new();
+        context.set_debug_sink(DebugSink::Collect(messages.clone()));
+
+        unsafe { gl::BindBufferBase(gl::SHADER_STORAGE_BUFFER, 0, 1_000_000) };
+
+        let messages = messages.take();
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].type_, gl::DEBUG_TYPE_ERROR);
+        assert_eq!(messages[0].severity, DebugSeverity::High);
+    }
+
+    #[test]
+    fn test_debug_sink_custom() {
+        let mut context = Context::new().unwrap();
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let sender = std::sync::Mutex::new(sender);
+        context.set_debug_sink(DebugSink::Custom(Box::new(move |message| {
+            sender.lock().unwrap().send(message.to_string()).unwrap()
+        })));
+
+        unsafe { gl::BindBufferBase(gl::SHADER_STORAGE_BUFFER, 0, 1_000_000) };
+
+        let message = receiver.try_recv().unwrap();
+        assert!(
+            message.contains("ERROR of HIGH severity, raised from API"),
+            "{}",
+            message
+        );
+    }
+
+    #[test]
+    fn test_debug_sink_log() {
+        struct Logger(Mutex<Vec<(log::Level, String)>>);
+        impl log::Log for Logger {
+            fn enabled(&self, _: &log::Metadata) -> bool {
+                true
+            }
+            fn log(&self, record: &log::Record) {
+                self.0
+                    .lock()
+                    .unwrap()
+                    .push((record.level(), record.args().to_string()));
+            }
+            fn flush(&self) {}
+        }
+        static LOGGER: Logger = Logger(Mutex::new(Vec::new()));
+        log::set_logger(&LOGGER).unwrap();
+        log::set_max_level(log::LevelFilter::Trace);
+
+        let mut context = Context::new().unwrap();
+        context.set_debug_sink(DebugSink::Log);
+
+        insert_message(gl::DEBUG_SEVERITY_MEDIUM, "medium message");
+        unsafe { gl::BindBufferBase(gl::SHADER_STORAGE_BUFFER, 0, 1_000_000) };
+
+        let records = std::mem::take(&mut *LOGGER.0.lock().unwrap());
+        assert_eq!(records.len(), 2, "{:?}", records);
+        assert_eq!(records[0].0, log::Level::Warn);
+        assert!(records[0].1.ends_with("medium message"), "{}", records[0].1);
+        assert_eq!(records[1].0, log::Level::Error);
+        assert!(
+            records[1]
+                .1
+                .contains("ERROR of HIGH severity, raised from API"),
+            "{}",
+            records[1].1
+        );
+    }
+
+    #[test]
+    fn test_debug_sink_panic_at() {
+        let mut context = Context::new().unwrap();
+        context.set_debug_sink(DebugSink::PanicAt(DebugSeverity::Medium));
+
+        // Below the threshold
+        insert_message(gl::DEBUG_SEVERITY_LOW, "low message");
+        context.check();
+
+        // Only the first message at or above the threshold is kept, and the
+        // callback returns normally
+        insert_message(gl::DEBUG_SEVERITY_MEDIUM, "first message");
+        unsafe { gl::BindBufferBase(gl::SHADER_STORAGE_BUFFER, 0, 1_000_000) };
+        let panic = std::panic::catch_unwind(super::check).unwrap_err();
+        let message = panic.downcast_ref::<String>().unwrap();
+        assert!(message.ends_with("first message"), "{}", message);
+
+        // The check clears it
+        context.check();
+    }
+
+    #[test]
+    #[should_panic(expected = "ERROR of HIGH severity, raised from API")]
+    fn test_debug_sink_panic_at_dispatch() {
+        let mut context = Context::new().unwrap();
+        context.set_debug_sink(DebugSink::PanicAt(DebugSeverity::High));
+        let source = "#version 450 core\nlayout(local_size_x = 1) in;\nvoid main() {}\n";
+        let source = std::ffi::CString::new(source).unwrap();
+        let shader = crate::Shader::from_source(&source, gl::COMPUTE_SHADER).unwrap();
+        let program = crate::Program::new(vec![(shader, gl::COMPUTE_SHADER)]).unwrap();
+
+        unsafe { gl::BindBufferBase(gl::SHADER_STORAGE_BUFFER, 0, 1_000_000) };
+        program.dispatch(1, 1, 1);
     }
 }
@@ -0,0 +1,88 @@
+use gl::types::*;
+use std::ffi::CStr;
+
+use crate::error::ShaderError;
+use crate::std430::{IVec2, IVec3, IVec4, UVec2, UVec3, UVec4, Vec2, Vec3, Vec4};
+use crate::Std430;
+
+/// A Rust type the generic kernels can work on, with the GLSL type it is
+/// stored as in a std430 buffer.
+///
+/// The kernels only use the operators `ScanOp` needs, so integer operators are
+/// not available for floats and vectors combine componentwise.
+pub trait Element: Std430 {
+    /// The GLSL type name, e.g. `uint`.
+    const GLSL_TYPE: &'static str;
+    /// The extension the GLSL type needs, if it is not core in GLSL 4.50.
+    const EXTENSION: Option<&'static str> = None;
+    /// A GLSL expression of the smallest value, the identity of `ScanOp::Max`.
+    const MIN: &'static str;
+    /// A GLSL expression of the largest value, the identity of `ScanOp::Min`.
+    const MAX: &'static str;
+
+    /// Whether the current context supports the type.
+    fn is_supported() -> bool {
+        Self::EXTENSION.is_none_or(has_extension)
+    }
+}
+
+macro_rules! impl_element {
+    ($($ty:ty: $glsl:expr, $extension:expr, min $min:expr, max $max:expr;)*) => {
+        $(
+            impl Element for $ty {
+                const GLSL_TYPE: &'static str = $glsl;
+                const EXTENSION: Option<&'static str> = $extension;
+                const MIN: &'static str = $min;
+                const MAX: &'static str = $max;
+            }
+        )*
+    };
+}
+
+impl_element! {
+    i32: "int", None, min "(-2147483647 - 1)", max "2147483647";
+    u32: "uint", None, min "0u", max "4294967295u";
+    // GLSL has no literal for infinity
+    f32: "float", None, min "uintBitsToFloat(0xFF800000u)",
+        max "uintBitsToFloat(0x7F800000u)";
+    f64: "double", None,
+        min "packDouble2x32(uvec2(0u, 0xFFF00000u))",
+        max "packDouble2x32(uvec2(0u, 0x7FF00000u))";
+    i64: "int64_t", Some("GL_ARB_gpu_shader_int64"),
+        min "(-9223372036854775807l - 1l)", max "9223372036854775807l";
+    u64: "uint64_t", Some("GL_ARB_gpu_shader_int64"),
+        min "0ul", max "18446744073709551615ul";
+    Vec2: "vec2", None, min "vec2(uintBitsToFloat(0xFF800000u))",
+        max "vec2(uintBitsToFloat(0x7F800000u))";
+    Vec3: "vec3", None, min "vec3(uintBitsToFloat(0xFF800000u))",
+        max "vec3(uintBitsToFloat(0x7F800000u))";
+    Vec4: "vec4", None, min "vec4(uintBitsToFloat(0xFF800000u))",
+        max "vec4(uintBitsToFloat(0x7F800000u))";
+    IVec2: "ivec2", None, min "ivec2(-2147483647 - 1)", max "ivec2(2147483647)";
+    IVec3: "ivec3", None, min "ivec3(-2147483647 - 1)", max "ivec3(2147483647)";
+    IVec4: "ivec4", None, min "ivec4(-2147483647 - 1)", max "ivec4(2147483647)";
+    UVec2: "uvec2", None, min "uvec2(0u)", max "uvec2(4294967295u)";
+    UVec3: "uvec3", None, min "uvec3(0u)", max "uvec3(4294967295u)";
+    UVec4: "uvec4", None, min "uvec4(0u)", max "uvec4(4294967295u)";
+}
+
+/// Fails with `MissingExtension` if the current context does not support `T`.
+pub(crate) fn check_supported<T: Element>() -> Result<(), ShaderError> {
+    if T::is_supported() {
+        Ok(())
+    } else {
+        Err(ShaderError::MissingExtension(
+            T::EXTENSION.unwrap_or_default().to_string(),
+        ))
+    }
+}
+
+/// Whether the current context exposes the GL extension `name`.
+pub(crate) fn has_extension(name: &str) -> bool {
+    let mut count = 0;
+    unsafe { gl::GetIntegerv(gl::NUM_EXTENSIONS, &mut count) };
+    (0..count as GLuint).any(|i| unsafe {
+        let ptr = gl::GetStringi(gl::EXTENSIONS, i);
+        !ptr.is_null() && CStr::from_ptr(ptr as *const _).to_bytes() == name.as_bytes()
+    })
+}
@@ -0,0 +1,300 @@
+use std::fmt;
+
+use crate::template::TemplateError;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ShaderError {
+    /// The driver rejected a shader. `source` is the source the diagnostics
+    /// refer to, used to render them.
+    Compile {
+        shader: String,
+        source: String,
+        diagnostics: Vec<Diagnostic>,
+    },
+    Link {
+        diagnostics: Vec<Diagnostic>,
+    },
+    Template(TemplateError),
+    /// The shader needs a GL extension the context does not have.
+    MissingExtension(String),
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// One message of a shader compiler or linker info log.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+    /// 1-based, if the driver gave one. Drivers write 0 for messages that are
+    /// not about a line, which is parsed as `None`.
+    pub line: Option<usize>,
+    /// 1-based, if the driver gave one. Only Mesa does.
+    pub column: Option<usize>,
+}
+
+impl Diagnostic {
+    /// Parses a driver info log, one diagnostic per line. The line formats of
+    /// Mesa (`0:12(5): error: ...`), NVIDIA (`0(12) : error C1008: ...`) and
+    /// AMD/Intel (`ERROR: 0:12: ...`) are recognized, anything else is kept as
+    /// an error without a location.
+    pub fn parse_info_log(log: &str) -> Vec<Diagnostic> {
+        log.trim_end_matches('\0')
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .map(|line| {
+                parse_mesa(line)
+                    .or_else(|| parse_nvidia(line))
+                    .or_else(|| parse_amd(line))
+                    .unwrap_or_else(|| {
+                        let (severity, message) =
+                            parse_severity(line).unwrap_or((Severity::Error, line));
+                        Diagnostic {
+                            severity,
+                            message: message.to_string(),
+                            line: None,
+                            column: None,
+                        }
+                    })
+            })
+            .collect()
+    }
+
+    /// Renders the diagnostic like rustc does, with the offending line of
+    /// `source` and a caret under the column.
+    pub fn render(&self, shader: &str, source: &str) -> String {
+        let mut rendered = format!("{}: {}\n", self.severity, self.message);
+        let line = match self.line {
+            Some(line) => line,
+            None => return rendered,
+        };
+
+        let column = self.column.unwrap_or(1);
+        let gutter = " ".repeat(line.to_string().len());
+        rendered += &format!("{}--> {}:{}:{}\n", gutter, shader, line, column);
+        // Lines and columns built by hand may still be 0
+        let code = line.checked_sub(1).and_then(|i| source.lines().nth(i));
+        if let Some(code) = code {
+            rendered += &format!("{} |\n", gutter);
+            rendered += &format!("{} | {}\n", line, code);
+            if let Some(indent) = self.column.and_then(|column| column.checked_sub(1)) {
+                rendered += &format!("{} | {}^\n", gutter, " ".repeat(indent));
+            }
+        }
+        rendered
+    }
+}
+
+fn parse_severity(text: &str) -> Option<(Severity, &str)> {
+    let lower = text.to_ascii_lowercase();
+    let (severity, rest) = if lower.starts_with("error") {
+        (Severity::Error, &text["error".len()..])
+    } else if lower.starts_with("warning") {
+        (Severity::Warning, &text["warning".len()..])
+    } else {
+        return None;
+    };
+    Some((severity, rest.trim_start_matches(':').trim()))
+}
+
+/// A 1-based line or column, 0 meaning none.
+fn position(number: usize) -> Option<usize> {
+    (number > 0).then_some(number)
+}
+
+/// Splits a leading decimal number off `text`.
+fn parse_number(text: &str) -> Option<(usize, &str)> {
+    let end = text
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(text.len());
+    Some((text[..end].parse().ok()?, &text[end..]))
+}
+
+// 0:12(5): error: `y' undeclared
+fn parse_mesa(text: &str) -> Option<Diagnostic> {
+    let (_, rest) = parse_number(text)?;
+    let (line, rest) = parse_number(rest.strip_prefix(':')?)?;
+    let (column, rest) = parse_number(rest.strip_prefix('(')?)?;
+    let (severity, message) = parse_severity(rest.strip_prefix("):")?.trim_start())?;
+    Some(Diagnostic {
+        severity,
+        message: message.to_string(),
+        line: position(line),
+        column: position(column),
+    })
+}
+
+// 0(12) : error C1008: undefined variable "y"
+fn parse_nvidia(text: &str) -> Option<Diagnostic> {
+    let (_, rest) = parse_number(text)?;
+    let (line, rest) = parse_number(rest.strip_prefix('(')?)?;
+    let (severity, message) = parse_severity(rest.strip_prefix(") :")?.trim_start())?;
+    // Drop the error code, e.g. C1008
+    let message = match message.split_once(": ") {
+        Some((code, message)) if code.starts_with('C') => message,
+        _ => message,
+    };
+    Some(Diagnostic {
+        severity,
+        message: message.to_string(),
+        line: position(line),
+        column: None,
+    })
+}
+
+// ERROR: 0:12: 'y' : undeclared identifier
+fn parse_amd(text: &str) -> Option<Diagnostic> {
+    let (severity, rest) = parse_severity(text)?;
+    let (_, rest) = parse_number(rest)?;
+    let (line, rest) = parse_number(rest.strip_prefix(':')?)?;
+    Some(Diagnostic {
+        severity,
+        message: rest.strip_prefix(':')?.trim().to_string(),
+        line: position(line),
+        column: None,
+    })
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+        }
+    }
+}
+
+impl fmt::Display for ShaderError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ShaderError::Compile {
+                shader,
+                source,
+                diagnostics,
+            } => {
+                for diagnostic in diagnostics {
+                    writeln!(f, "{}", diagnostic.render(shader, source))?;
+                }
+                write!(f, "could not compile `{}`", shader)
+            }
+            ShaderError::Link { diagnostics } => {
+                for diagnostic in diagnostics {
+                    writeln!(f, "{}", diagnostic.render("", ""))?;
+                }
+                write!(f, "could not link program")
+            }
+            ShaderError::Template(err) => err.fmt(f),
+            ShaderError::MissingExtension(extension) => {
+                write!(f, "the context does not support {}", extension)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ShaderError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ShaderError::Template(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<TemplateError> for ShaderError {
+    fn from(err: TemplateError) -> Self {
+        ShaderError::Template(err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Diagnostic, Severity};
+
+    #[test]
+    fn test_parse_info_log() {
+        let log = "0:6(2): error: `y' undeclared\n\
+                   0(12) : warning C7050: \"x\" might be used before being initialized\n\
+                   ERROR: 0:3: 'z' : undeclared identifier\n\
+                   error: unresolved reference to function `f'\n\
+                   0:0(0): error: no function with name 'main'\n\0";
+
+        assert_eq!(
+            Diagnostic::parse_info_log(log),
+            vec![
+                Diagnostic {
+                    severity: Severity::Error,
+                    message: "`y' undeclared".to_string(),
+                    line: Some(6),
+                    column: Some(2),
+                },
+                Diagnostic {
+                    severity: Severity::Warning,
+                    message: "\"x\" might be used before being initialized".to_string(),
+                    line: Some(12),
+                    column: None,
+                },
+                Diagnostic {
+                    severity: Severity::Error,
+                    message: "'z' : undeclared identifier".to_string(),
+                    line: Some(3),
+                    column: None,
+                },
+                Diagnostic {
+                    severity: Severity::Error,
+                    message: "unresolved reference to function `f'".to_string(),
+                    line: None,
+                    column: None,
+                },
+                Diagnostic {
+                    severity: Severity::Error,
+                    message: "no function with name 'main'".to_string(),
+                    line: None,
+                    column: None,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_render_diagnostic() {
+        let source = "#version 450 core\nvoid main() {\n  y = 1;\n}\n";
+        let diagnostic = Diagnostic {
+            severity: Severity::Error,
+            message: "`y' undeclared".to_string(),
+            line: Some(3),
+            column: Some(3),
+        };
+
+        assert_eq!(
+            diagnostic.render("test.comp.glsl", source),
+            "error: `y' undeclared\n \
+             --> test.comp.glsl:3:3\n  \
+             |\n\
+             3 |   y = 1;\n  \
+             |   ^\n"
+        );
+
+        // Logs without a position, and positions of 0 built by hand
+        let log = "0:0(0): error: no function with name 'main'\n";
+        let diagnostics = Diagnostic::parse_info_log(log);
+        assert_eq!(
+            diagnostics[0].render("test.comp.glsl", source),
+            "error: no function with name 'main'\n"
+        );
+        let diagnostic = Diagnostic {
+            line: Some(0),
+            column: Some(0),
+            ..diagnostic
+        };
+        assert_eq!(
+            diagnostic.render("test.comp.glsl", source),
+            "error: `y' undeclared\n \
+             --> test.comp.glsl:0:0\n"
+        );
+    }
+}
@@ -0,0 +1,187 @@
+use gl::types::*;
+use std::collections::HashMap;
+use std::marker::PhantomData;
+
+use crate::element::{check_supported, Element};
+use crate::error::ShaderError;
+use crate::scan::kernel;
+use crate::template::Param;
+use crate::{Buffer, Program};
+
+// The largest number of work groups a histogram is counted with, see
+// histogram.comp.glsl
+const MAX_WORK_GROUPS: usize = 1024;
+
+/// How `Histogram` finds the bin of an element.
+#[derive(Debug, Clone, PartialEq)]
+pub enum BinFn {
+    /// `bins` bins of the same width from `min` to `max`, for scalars. Elements
+    /// below `min` go to the first bin and elements from `max` up to the last.
+    Uniform { min: f32, max: f32 },
+    /// The element is its bin, for integer scalars. Elements past the last bin
+    /// are not counted.
+    Key,
+    /// A GLSL expression of the element `x` giving its bin as a `uint`, e.g.
+    /// `(x >> 4) & 15u` for the second digit of a radix sort. Elements past the
+    /// last bin are not counted.
+    Custom(String),
+}
+
+impl BinFn {
+    fn glsl(&self) -> &str {
+        match self {
+            BinFn::Uniform { .. } => {
+                "uint(clamp(floor((x - range_min) * range_scale), 0.0, float(BINS - 1)))"
+            }
+            BinFn::Key => "uint(x)",
+            BinFn::Custom(bin) => bin,
+        }
+    }
+}
+
+/// Counts the elements of a buffer falling in each bin, with atomics on a
+/// histogram private to each work group in shared memory, which are then added
+/// to the global one.
+pub struct Histogram<T: Element> {
+    bins: usize,
+    bin_fn: BinFn,
+    program: Program,
+    _marker: PhantomData<T>,
+}
+
+impl<T: Element> Histogram<T> {
+    /// Histograms of up to 4096 bins are privatized in shared memory, larger
+    /// ones are counted in global memory directly.
+    pub fn new(bins: usize, bin_fn: BinFn) -> Result<Self, ShaderError> {
+        assert!(bins > 0, "a histogram needs at least one bin");
+        check_supported::<T>()?;
+
+        let mut substs: HashMap<&str, Param> = HashMap::new();
+        substs.insert("TYPE", T::GLSL_TYPE.into());
+        substs.insert("BINS", bins.into());
+        substs.insert("BIN", bin_fn.glsl().into());
+        let program = kernel(
+            "histogram/histogram.comp.glsl",
+            include_str!("../shaders/histogram/histogram.comp.glsl"),
+            &substs,
+        )?;
+
+        if let BinFn::Uniform { min, max } = bin_fn {
+            assert!(min < max, "the range {}..{} is empty", min, max);
+            unsafe {
+                gl::ProgramUniform1f(program.get_id(), 1, min);
+                gl::ProgramUniform1f(program.get_id(), 2, bins as f32 / (max - min));
+            }
+        }
+
+        Ok(Histogram {
+            bins,
+            bin_fn,
+            program,
+            _marker: PhantomData,
+        })
+    }
+
+    pub fn bins(&self) -> usize {
+        self.bins
+    }
+
+    pub fn bin_fn(&self) -> &BinFn {
+        &self.bin_fn
+    }
+
+    /// Returns the number of elements of `input` in each bin.
+    pub fn run(&self, input: &Buffer<T>) -> Buffer<GLuint> {
+        let histogram = Buffer::new(self.bins);
+        if input.is_empty() {
+            return histogram;
+        }
+
+        let threads = self.program.work_group_size()[0] as usize;
+        let work_groups = input.len().div_ceil(threads).min(MAX_WORK_GROUPS);
+        unsafe { gl::ProgramUniform1ui(self.program.get_id(), 0, input.len() as GLuint) };
+        input.bind(0);
+        histogram.bind(1);
+        self.program.dispatch(work_groups as GLuint, 1, 1);
+        histogram
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BinFn, Histogram};
+    use crate::{Buffer, Context};
+    use rand::Rng;
+
+    fn histogram<T: Copy>(input: &[T], bins: usize, bin: impl Fn(T) -> usize) -> Vec<u32> {
+        let mut counts = vec![0; bins];
+        for &x in input {
+            if let Some(count) = counts.get_mut(bin(x)) {
+                *count += 1;
+            }
+        }
+        counts
+    }
+
+    #[test]
+    fn test_histogram_keys() {
+        let _context = Context::new().unwrap();
+        let mut rng = rand::thread_rng();
+
+        for &bins in &[1, 16, 256, 4096, 10_000] {
+            let keys = Histogram::<u32>::new(bins, BinFn::Key).unwrap();
+
+            // Uniform, skewed towards the first bins as in Zipf's law, and all
+            // in a single bin, which contends the most
+            let uniform: Vec<u32> = (0..100_000)
+                .map(|_| rng.gen_range(0, bins as u32 + 10))
+                .collect();
+            let skewed: Vec<u32> = (0..100_000)
+                .map(|_| (bins as f64 / rng.gen_range(1.0, bins as f64 + 1.0)) as u32)
+                .collect();
+            let single = vec![bins as u32 / 2; 100_000];
+
+            for input in [uniform, skewed, single] {
+                assert_eq!(
+                    keys.run(&Buffer::from_slice(&input)).to_vec(),
+                    histogram(&input, bins, |x| x as usize),
+                    "{} bins",
+                    bins
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_histogram_uniform_range() {
+        let _context = Context::new().unwrap();
+        let mut rng = rand::thread_rng();
+
+        // The luminance of an image, mostly dark, for tone mapping
+        let luminance = Histogram::<f32>::new(64, BinFn::Uniform { min: 0.0, max: 4.0 }).unwrap();
+        let input: Vec<f32> = (0..200_000)
+            .map(|_| rng.gen_range(0.0f32, 1.0).powi(6) * 5.0 - 0.1)
+            .collect();
+        let bin = |x: f32| (x * 16.0).floor().clamp(0.0, 63.0) as usize;
+        let counts = luminance.run(&Buffer::from_slice(&input)).to_vec();
+        assert_eq!(counts, histogram(&input, 64, bin));
+        assert_eq!(counts.iter().sum::<u32>(), 200_000);
+    }
+
+    #[test]
+    fn test_histogram_custom() {
+        let _context = Context::new().unwrap();
+        let mut rng = rand::thread_rng();
+
+        let digits =
+            Histogram::<u32>::new(16, BinFn::Custom("(x >> 4) & 15u".to_string())).unwrap();
+        let input: Vec<u32> = (0..1000).map(|_| rng.gen()).collect();
+        assert_eq!(
+            digits.run(&Buffer::from_slice(&input)).to_vec(),
+            histogram(&input, 16, |x| ((x >> 4) & 15) as usize)
+        );
+
+        let empty = digits.run(&Buffer::from_slice(&[]));
+        assert_eq!(empty.to_vec(), vec![0; 16]);
+    }
+}
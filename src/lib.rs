@@ -1,175 +1,98 @@
 // https://landonthomas.net/docs/gpu_compute_model_terms_quick_ref.pdf
 // For a quick GPU compute terminology rosetta stone.
 // In the comments I often mix GLSL and NVIDIA's terminology so this should help
+mod barrier;
+mod buffer;
+mod cache;
+mod compact;
+mod context;
 mod debug_message_callback;
+mod element;
+mod error;
+mod histogram;
+mod profiler;
 mod program;
+mod radix_sort;
+mod reduce;
+mod reflection;
+mod runs;
+mod scan;
+mod segmented_scan;
 mod shader;
+pub mod std430;
+mod template;
+
+// Lets the code generated by the derive macros refer to `::compute_shader` from
+// inside this crate too
+extern crate self as compute_shader;
+
+pub use crate::barrier::BufferAccess;
+pub use crate::buffer::Buffer;
+pub use crate::cache::ProgramCache;
+pub use crate::compact::{compact, partition, Compact, CompactCounter, Compacted};
+pub use crate::context::Context;
+pub use crate::debug_message_callback::{DebugMessage, DebugMessages, DebugSeverity, DebugSink};
+pub use crate::element::Element;
+pub use crate::error::{Diagnostic, Severity, ShaderError};
+pub use crate::histogram::{BinFn, Histogram};
+pub use crate::profiler::{Profiler, Report, ScopeTimings, Stats};
+pub use crate::program::Program;
+pub use crate::radix_sort::{radix_sort, RadixKey, RadixSort};
+pub use crate::reduce::{ArgOp, ArgReduce, Reduce};
+pub use crate::reflection::{BufferBlock, BufferVariable, Uniform};
+pub use crate::runs::{reduce_by_key, run_length_encode, unique, ReduceByKey, Runs};
+pub use crate::scan::{Scan, ScanAlgorithm, ScanKind, ScanOp};
+pub use crate::segmented_scan::SegmentedScan;
+pub use crate::shader::Shader;
+pub use crate::std430::Std430;
+pub use crate::template::{Param, ShaderTemplate, TemplateError};
+pub use compute_shader_derive::Std430;
 
 #[cfg(test)]
 mod tests {
     use gl::types::*;
-    use glsl::parser::Parse;
-    use glsl::syntax::PreprocessorDefine;
-    use glsl::visitor::Visit;
-    use glsl::visitor::{Host, Visitor};
     use std::collections::HashMap;
 
-    type GLvec4 = [GLfloat; 4];
-    type GLuvec4 = [GLuint; 4];
-
-    use std::ffi::CString;
-
-    use glfw::Context;
-
-    use crate::debug_message_callback;
+    use crate::error::ShaderError;
     use crate::program::Program;
-    use crate::shader;
-    use glsl::syntax::ShaderStage;
+    use crate::std430::{UVec4, Vec4};
+    use crate::template::{Param, ShaderTemplate};
+    use crate::{
+        unique, Buffer, Compact, CompactCounter, Compacted, Context, DebugMessage, DebugMessages,
+        DebugSink, Profiler, ProgramCache, Scan, ScanKind, ScanOp, Std430,
+    };
 
     const RELATIVE_TOLERANCE: f32 = 1e-8;
 
-    fn make_opengl_window() -> glfw::Window {
-        let mut glfw = glfw::init(glfw::FAIL_ON_ERRORS).unwrap();
-        glfw.window_hint(glfw::WindowHint::Visible(false));
-        glfw.window_hint(glfw::WindowHint::ContextVersion(4, 6));
-        glfw.window_hint(glfw::WindowHint::OpenGlForwardCompat(true));
-        glfw.window_hint(glfw::WindowHint::OpenGlDebugContext(true));
-
-        let (mut window, _) = glfw
-            .create_window(300, 300, "Hello this is window", glfw::WindowMode::Windowed)
-            .expect("Failed to create GLFW window.");
-        window.make_current();
-        gl::load_with(|s| window.get_proc_address(s));
-
-        unsafe {
-            gl::Enable(gl::DEBUG_OUTPUT_SYNCHRONOUS);
-            gl::DebugMessageCallback(Some(debug_message_callback::callback), std::ptr::null())
-        }
-
-        return window;
-    }
-
-    fn make_shader_src<'a>(src: &str, substs: &HashMap<&'a str, usize>) -> String {
-        let mut shader = ShaderStage::parse(src).unwrap();
-
-        let mut transformed_source = String::new();
-        struct MyVisitor<'a> {
-            substs: &'a HashMap<&'a str, usize>,
-        }
-        impl<'a> Visitor for MyVisitor<'a> {
-            fn visit_preprocessor_define(&mut self, define: &mut PreprocessorDefine) -> Visit {
-                match define {
-                    PreprocessorDefine::ObjectLike { ident, value } if value == "-1337" => {
-                        *value = self.substs[ident.as_str()].to_string()
-                    }
-                    _ => (),
-                };
-
-                Visit::Parent
-            }
-        }
-
-        let mut my_visitor = MyVisitor { substs };
-        shader.visit(&mut my_visitor);
-
-        glsl::transpiler::glsl::show_translation_unit(&mut transformed_source, &shader);
-
-        transformed_source
-    }
-
-    fn make_compute_shader_program(source: &str, substs: &HashMap<&str, usize>) -> Program {
-        let kernel = shader::Shader::from_source(
-            &CString::new(make_shader_src(source, &substs)).unwrap(),
-            gl::COMPUTE_SHADER,
-        )
-        .unwrap();
-        match Program::new(vec![(kernel, gl::COMPUTE_SHADER)]) {
-            Ok(res) => res,
-            Err(err) => {
-                println!("{}", err);
-                std::process::exit(1);
-            }
-        }
-    }
-
-    fn get_ssbo<T: Clone>(buffer: GLuint) -> T {
-        unsafe {
-            gl::BindBuffer(gl::SHADER_STORAGE_BUFFER, buffer);
-            let input_data = gl::MapBuffer(gl::SHADER_STORAGE_BUFFER, gl::READ_ONLY) as *const T;
-
-            gl::UnmapBuffer(gl::SHADER_STORAGE_BUFFER);
-            gl::BindBuffer(gl::SHADER_STORAGE_BUFFER, 0);
-
-            return (*input_data).clone();
-        }
-    }
-
-    fn make_input_ssbo<T>(input_data: &T) -> GLuint {
-        let mut ssbo = 0;
-        let index_binding_point = 0;
-        unsafe {
-            gl::GenBuffers(1, &mut ssbo);
-            gl::BindBuffer(gl::SHADER_STORAGE_BUFFER, ssbo);
-            gl::NamedBufferData(
-                ssbo,
-                std::mem::size_of::<T>() as GLsizeiptr,
-                std::mem::transmute(input_data),
-                gl::DYNAMIC_READ,
-            );
-
-            gl::BindBufferBase(gl::SHADER_STORAGE_BUFFER, index_binding_point, ssbo);
-
-            gl::BindBuffer(gl::SHADER_STORAGE_BUFFER, 0);
-        }
-        return ssbo;
-    }
-
-    fn make_output_ssbo<T>() -> GLuint {
-        let mut ssbo = 0;
-        let index_binding_point = 1;
-        unsafe {
-            gl::GenBuffers(1, &mut ssbo);
-            gl::BindBuffer(gl::SHADER_STORAGE_BUFFER, ssbo);
-            gl::BufferData(
-                gl::SHADER_STORAGE_BUFFER,
-                std::mem::size_of::<T>() as GLsizeiptr,
-                std::mem::transmute(std::ptr::null::<T>()),
-                gl::DYNAMIC_READ,
-            );
-
-            gl::BindBufferBase(gl::SHADER_STORAGE_BUFFER, index_binding_point, ssbo);
-
-            gl::BindBuffer(gl::SHADER_STORAGE_BUFFER, 0);
-        }
-        return ssbo;
+    fn make_compute_shader_program(
+        name: &str,
+        source: &str,
+        substs: &HashMap<&str, Param>,
+    ) -> Program {
+        let cache = ProgramCache::new(std::env::temp_dir().join("compute-shader-tests"));
+        ShaderTemplate::parse(name, source)
+            .map_err(ShaderError::from)
+            .and_then(|template| cache.compute_program(&template, substs))
+            .unwrap_or_else(|err| panic!("{}", err))
     }
 
     #[test]
     fn test_single_wg_prefix_sum() {
         // Maximum number of threads is 1024 and each thread processes 2 elements
         const DATA_LEN: usize = 2048;
-        #[derive(Debug, Copy, Clone)]
-        #[repr(C, packed)]
-        struct InputData {
-            data: [GLfloat; DATA_LEN],
-        }
-
-        #[derive(Debug, Copy, Clone)]
-        #[repr(C, packed)]
-        struct OutputData {
-            length: GLuint,
-            data: [GLfloat; DATA_LEN],
-        }
 
         // *************************************************************************
         // Create OpenGL Context
-        let _window = make_opengl_window();
+        let mut context = Context::new().unwrap();
+        let messages = DebugMessages::new();
+        context.set_debug_sink(DebugSink::Collect(messages.clone()));
 
         // *************************************************************************
         // Load shader and create program
-        let mut substs = std::collections::HashMap::new();
-        substs.insert("N", DATA_LEN);
+        let mut substs: HashMap<&str, Param> = HashMap::new();
+        substs.insert("N", DATA_LEN.into());
         let program = make_compute_shader_program(
+            "single_wg_prefix_sum/prefix_sum.comp.glsl",
             include_str!(concat!(
                 env!("CARGO_MANIFEST_DIR"),
                 "/shaders/single_wg_prefix_sum/prefix_sum.comp.glsl"
@@ -179,70 +102,52 @@ mod tests {
 
         // *************************************************************************
         // Create random data
-        let input_data = InputData {
-            data: [1.0; DATA_LEN],
-        };
+        let input_data: Vec<GLfloat> = vec![1.0; DATA_LEN];
 
         // *************************************************************************
         // Calculate expected result
-        let mut expected = [0.0; DATA_LEN];
+        // The kernel computes an exclusive scan
+        let mut expected = vec![0.0; DATA_LEN];
         for i in 1..DATA_LEN {
-            expected[i] += expected[i - 1] + input_data.data[i];
+            expected[i] = expected[i - 1] + input_data[i - 1];
         }
 
         // *************************************************************************
         // Create input and output SSBOs
-        let input_ssbo = make_input_ssbo(&input_data);
+        let input_ssbo = Buffer::from_slice(&input_data);
+        input_ssbo.bind(0);
 
         // *************************************************************************
         // Run compute shader
-        program.use_();
-
-        unsafe {
-            gl::DispatchCompute(1, 1, 1);
-            gl::MemoryBarrier(gl::BUFFER_UPDATE_BARRIER_BIT);
-        };
+        program.dispatch(1, 1, 1);
 
         // *************************************************************************
         // Check expected result matches with output
 
-        let result = get_ssbo::<InputData>(input_ssbo);
-        for i in 0..DATA_LEN {
-            let result_value = result.data[i];
-            assert_eq!(expected[i], result_value);
-        }
-
-        // *************************************************************************
-        // Cleanup
-        unsafe { gl::DeleteBuffers(1, &input_ssbo) };
+        assert_eq!(input_ssbo.to_vec(), expected);
+        let errors: Vec<DebugMessage> = messages
+            .take()
+            .into_iter()
+            .filter(|message| message.type_ == gl::DEBUG_TYPE_ERROR)
+            .collect();
+        assert_eq!(errors, vec![]);
     }
 
     #[test]
     fn test_single_wg_compaction() {
         const DATA_LEN: usize = 2048;
-        #[derive(Debug, Copy, Clone)]
-        #[repr(C, packed)]
-        struct InputData {
-            data: [GLfloat; DATA_LEN],
-        }
-
-        #[derive(Debug, Copy, Clone)]
-        #[repr(C, packed)]
-        struct OutputData {
-            length: GLuint,
-            data: [GLfloat; DATA_LEN],
-        }
 
         // *************************************************************************
         // Create OpenGL Context
-        let _window = make_opengl_window();
+        let _context = Context::new().unwrap();
 
         // *************************************************************************
         // Load shader and create program
 
-        let mut substs = std::collections::HashMap::new();
-        substs.insert("N", DATA_LEN);
+        let mut substs: HashMap<&str, Param> = HashMap::new();
+        substs.insert("N", DATA_LEN.into());
         let program = make_compute_shader_program(
+            "single_wg_compaction/compaction.comp.glsl",
             include_str!(concat!(
                 env!("CARGO_MANIFEST_DIR"),
                 "/shaders/single_wg_compaction/compaction.comp.glsl"
@@ -252,213 +157,156 @@ mod tests {
 
         // *************************************************************************
         // Create random data
-        let input_data = InputData {
-            data: {
-                let mut data = [0.0; DATA_LEN];
-                for i in 0..DATA_LEN {
-                    data[i] = i as GLfloat + 1.0;
-                }
-                data
-            },
-        };
+        let input_data: Vec<GLfloat> = (0..DATA_LEN).map(|i| i as GLfloat + 1.0).collect();
 
         // *************************************************************************
         // Calculate expected result
 
         let mut expected = [0.0; DATA_LEN];
-        for i in 0..(DATA_LEN / 2) {
-            expected[i] = 2.0 * (i + 1) as GLfloat;
+        for (i, e) in expected.iter_mut().take(DATA_LEN / 2).enumerate() {
+            *e = 2.0 * (i + 1) as GLfloat;
         }
 
         // *************************************************************************
         // Create input and output SSBOs
-        let input_ssbo = make_input_ssbo(&input_data);
-        let output_ssbo = make_output_ssbo::<OutputData>();
+        let input_ssbo = Buffer::from_slice(&input_data);
+        let output = Compacted {
+            data: Buffer::<GLfloat>::new(DATA_LEN),
+            counter: Buffer::new(1),
+        };
+        input_ssbo.bind(0);
+        output.data.bind(1);
+        output.counter.bind(2);
 
         // *************************************************************************
         // Run compute shader
-        program.use_();
-
-        unsafe {
-            gl::DispatchCompute(1, 1, 1);
-            gl::MemoryBarrier(gl::BUFFER_UPDATE_BARRIER_BIT);
-        };
+        program.dispatch(1, 1, 1);
 
         // *************************************************************************
         // Check expected result matches with output
-        let output_struct = get_ssbo::<OutputData>(output_ssbo);
-
-        assert_eq!(output_struct.length as usize, DATA_LEN / 2);
-        for i in 0..DATA_LEN {
-            let output_value = output_struct.data[i];
-            assert!((expected[i] - output_value).abs() <= (RELATIVE_TOLERANCE * output_value));
+        assert_eq!(output.len(), DATA_LEN / 2);
+        for (expected_value, output_value) in expected.iter().zip(output.data.to_vec().iter()) {
+            assert!((expected_value - output_value).abs() <= (RELATIVE_TOLERANCE * output_value));
         }
 
-        // *************************************************************************
-        // Cleanup
-        unsafe { gl::DeleteBuffers(1, &input_ssbo) };
+        // Nothing kept
+        substs.insert("PREDICATE", false.into());
+        let none = make_compute_shader_program(
+            "single_wg_compaction/compaction.comp.glsl",
+            include_str!(concat!(
+                env!("CARGO_MANIFEST_DIR"),
+                "/shaders/single_wg_compaction/compaction.comp.glsl"
+            )),
+            &substs,
+        );
+        let output = Compacted {
+            data: Buffer::<GLfloat>::new(DATA_LEN),
+            counter: Buffer::new(1),
+        };
+        output.data.bind(1);
+        output.counter.bind(2);
+        none.dispatch(1, 1, 1);
+        assert!(output.is_empty());
     }
 
     #[test]
     fn test_multiple_wg_prefix_sum() {
-        // TODO(Andrea): understand why it doesn't work for non powers of 2 =>
-        // It needs to be padded to closest multiple of two
-        // See https://developer.nvidia.com/gpugems/gpugems3/part-vi-gpu-computing/chapter-39-parallel-prefix-sum-scan-cuda
-        // 39.2.4 Arrays of Arbitrary Size
-        const DATA_LEN: usize = 262_144;
-        const WORK_GROUPS: usize = 128;
-
-        #[derive(Debug, Copy, Clone)]
-        #[repr(C, packed)]
-        struct InputData {
-            data: [GLfloat; DATA_LEN],
-        }
-
-        #[derive(Debug, Copy, Clone)]
-        #[repr(C, packed)]
-        struct OutputData {
-            sums: [GLfloat; WORK_GROUPS],
-            data: [GLfloat; DATA_LEN],
-        }
+        // Not a multiple of the block length, the last block is padded on the GPU
+        const DATA_LEN: usize = 262_147;
 
         // *************************************************************************
         // Create OpenGL Context
-        let _window = make_opengl_window();
+        let _context = Context::new().unwrap();
 
         // *************************************************************************
         // Load shader and create program
-
-        let mut substs = std::collections::HashMap::new();
-        substs.insert("DATA_LEN", DATA_LEN);
-        substs.insert("WORK_GROUPS", WORK_GROUPS);
-        let program1 = make_compute_shader_program(
-            include_str!(concat!(
-                env!("CARGO_MANIFEST_DIR"),
-                "/shaders/multi_wg_prefix_sum/multi_wg_prefix_sum1.comp.glsl"
-            )),
-            &substs,
-        );
-        let program2 = make_compute_shader_program(
-            include_str!(concat!(
-                env!("CARGO_MANIFEST_DIR"),
-                "/shaders/multi_wg_prefix_sum/multi_wg_prefix_sum2.comp.glsl"
-            )),
-            &substs,
-        );
+        let scan = Scan::<GLfloat>::new(ScanOp::Add).unwrap();
 
         // *************************************************************************
         // Create random data
-        let input_data = InputData {
-            data: [1.0; DATA_LEN],
-        };
+        let input_data: Vec<GLfloat> = vec![1.0; DATA_LEN];
 
         // *************************************************************************
         // Calculate expected result
-        let mut expected = [0.0; DATA_LEN];
+        // An exclusive scan, see ScanKind::Exclusive
+        let mut expected = vec![0.0; DATA_LEN];
         for i in 1..DATA_LEN {
-            expected[i] += expected[i - 1] + input_data.data[i];
+            expected[i] = expected[i - 1] + input_data[i - 1];
         }
 
         // *************************************************************************
         // Create input and output SSBOs
-        let input_ssbo = make_input_ssbo(&input_data);
-        let output_ssbo = make_output_ssbo::<OutputData>();
+        let input_ssbo = Buffer::from_slice(&input_data);
 
         // *************************************************************************
         // Run compute shader
-        program1.use_();
-        unsafe {
-            gl::DispatchCompute(WORK_GROUPS as GLuint, 1, 1);
-            gl::MemoryBarrier(gl::BUFFER_UPDATE_BARRIER_BIT);
-        };
-
-        fn inplace_exclusive_prefix_sum(a: &mut [GLfloat; WORK_GROUPS]) {
-            let mut v = a.to_vec();
-            v.insert(0, 0.);
-            for i in 1..v.len() {
-                v[i] += v[i - 1];
-            }
-            v.pop();
-            for i in 0..v.len() {
-                a[i] = v[i];
-            }
-        }
-
-        // TODO(Andrea): should this be a GPU kernel to avoid moving memory?
-        unsafe {
-            gl::BindBuffer(gl::SHADER_STORAGE_BUFFER, output_ssbo);
-
-            let ptr = gl::MapBuffer(gl::SHADER_STORAGE_BUFFER, gl::READ_ONLY) as *mut OutputData;
-
-            inplace_exclusive_prefix_sum(&mut ((*ptr).sums));
-
-            gl::UnmapBuffer(gl::SHADER_STORAGE_BUFFER);
-            gl::BindBuffer(gl::SHADER_STORAGE_BUFFER, 0);
-        }
-
-        program2.use_();
-        unsafe {
-            gl::DispatchCompute(WORK_GROUPS as GLuint, 1, 1);
-            gl::MemoryBarrier(gl::BUFFER_UPDATE_BARRIER_BIT);
-        };
+        let mut profiler = Profiler::new();
+        let output_ssbo = profiler.scope("scan", || scan.run(&input_ssbo, ScanKind::Exclusive));
+        profiler.wait();
+        let report = profiler.report();
+        let timings = report.scope("scan").unwrap();
+        assert_eq!(timings.samples, 1);
+        assert!(timings.cpu.min > std::time::Duration::ZERO);
 
         // *************************************************************************
         // Check expected result matches with output
-        let output_struct = get_ssbo::<OutputData>(output_ssbo);
+        let output_data = output_ssbo.to_vec();
+        assert_eq!(output_data.len(), DATA_LEN);
 
-        for i in 0..DATA_LEN {
-            let output_value = output_struct.data[i];
-            assert!((expected[i] - output_value).abs() <= (RELATIVE_TOLERANCE * output_value));
+        for (expected_value, output_value) in expected.iter().zip(output_data.iter()) {
+            assert!((expected_value - output_value).abs() <= (RELATIVE_TOLERANCE * output_value));
         }
-
-        // *************************************************************************
-        // Cleanup
-        unsafe { gl::DeleteBuffers(1, &input_ssbo) };
     }
 
     #[test]
     fn test_single_wg_raycasting() {
         const CHUNK_ROWS: usize = 8;
         const CHUNK_COLS: usize = 8;
-        const CHUNK_SIZE: usize = CHUNK_ROWS * CHUNK_COLS;
 
         // See https://developer.nvidia.com/gpugems/gpugems3/part-vi-gpu-computing/chapter-39-parallel-prefix-sum-scan-cuda
         // 39.2.4 Arrays of Arbitrary Size
 
-        #[derive(Debug, Copy, Clone)]
-        #[repr(C, packed)]
+        #[derive(Debug, Copy, Clone, Std430)]
         struct InputData {
-            chunk: [GLuint; CHUNK_SIZE],
-            ray_start: GLvec4,
-            ray_direction: GLvec4,
+            chunk: [[GLuint; CHUNK_COLS]; CHUNK_ROWS],
+            ray_start: Vec4,
+            ray_direction: Vec4,
         }
 
-        #[derive(Debug, Copy, Clone)]
-        #[repr(C, packed)]
+        #[derive(Debug, Copy, Clone, Std430)]
         struct OutputData {
-            hit: GLvec4,
-            has_hit: GLboolean,
+            hit: Vec4,
+            has_hit: bool,
         }
 
         // *************************************************************************
         // Create OpenGL Context
-        let _window = make_opengl_window();
+        let _context = Context::new().unwrap();
 
         // *************************************************************************
         // Load shader and create program
 
-        let mut substs = std::collections::HashMap::new();
-        substs.insert("CHUNK_ROWS", CHUNK_ROWS);
-        substs.insert("CHUNK_COLS", CHUNK_COLS);
-        substs.insert("MAX_ITERS", 100);
-        substs.insert("THREADS", 1);
+        let mut substs: HashMap<&str, Param> = HashMap::new();
+        substs.insert("CHUNK_ROWS", CHUNK_ROWS.into());
+        substs.insert("CHUNK_COLS", CHUNK_COLS.into());
+        substs.insert("MAX_ITERS", 100.into());
+        substs.insert("THREADS", 1.into());
         let program = make_compute_shader_program(
+            "single_wg_raycasting/single_wg_raycasting.comp.glsl",
             include_str!(concat!(
                 env!("CARGO_MANIFEST_DIR"),
                 "/shaders/single_wg_raycasting/single_wg_raycasting.comp.glsl"
             )),
             &substs,
         );
+        // Fail before dispatching if the Rust structs don't match the blocks
+        for block in program.buffer_blocks() {
+            match block.name.as_str() {
+                "InputData" => block.check_layout::<InputData>().unwrap(),
+                "OutputData" => block.check_layout::<OutputData>().unwrap(),
+                _ => unreachable!(),
+            }
+        }
 
         // *************************************************************************
         // Create random data
@@ -475,9 +323,9 @@ mod tests {
         ];
 
         let input_data = InputData {
-            chunk: unsafe { std::mem::transmute(chunk) },
-            ray_start: [0.0, 0.0, 0.0, 0.0],
-            ray_direction: [1.0, 1.0, 0.0, 0.0],
+            chunk,
+            ray_start: Vec4([0.0, 0.0, 0.0, 0.0]),
+            ray_direction: Vec4([1.0, 1.0, 0.0, 0.0]),
         };
 
         // *************************************************************************
@@ -485,34 +333,46 @@ mod tests {
 
         // *************************************************************************
         // Create input and output SSBOs
-        let input_ssbo = make_input_ssbo(&input_data);
-        let output_ssbo = make_output_ssbo::<OutputData>();
+        let input_ssbo = Buffer::from_slice(&[input_data]);
+        let output_ssbo = Buffer::<OutputData>::new(1);
+        input_ssbo.bind(0);
+        output_ssbo.bind(1);
 
         // *************************************************************************
         // Run compute shader
-        program.use_();
-        unsafe {
-            gl::DispatchCompute(1, 1, 1);
-            gl::MemoryBarrier(gl::BUFFER_UPDATE_BARRIER_BIT);
-        };
+        program.dispatch(1, 1, 1);
 
         // *************************************************************************
         // Check expected result matches with output
-        let output_struct = get_ssbo::<OutputData>(output_ssbo);
-        let hit = output_struct.hit;
-        assert_eq!(hit, [4.0, 4.0, 0.0, 0.0]);
-        assert_eq!(output_struct.has_hit, 1);
-
-        // *************************************************************************
-        // Cleanup
-        unsafe { gl::DeleteBuffers(1, &input_ssbo) };
+        let output_struct = output_ssbo.to_vec()[0];
+        assert_eq!(output_struct.hit, Vec4([4.0, 4.0, 0.0, 0.0]));
+        assert!(output_struct.has_hit);
     }
 
     #[test]
     fn test_multiple_wg_compaction() {
-        // TODO(Andrea): understand why it doesn't work for non powers of 2 =>
-        // It needs to be padded to closest multiple of two
+        check_multiple_wg_compaction(
+            "multi_wg_compaction/multi_wg_compaction1.comp.glsl",
+            include_str!(concat!(
+                env!("CARGO_MANIFEST_DIR"),
+                "/shaders/multi_wg_compaction/multi_wg_compaction1.comp.glsl"
+            )),
+        );
+    }
+
+    #[test]
+    fn test_multiple_wg_compaction_conflict_free() {
+        check_multiple_wg_compaction(
+            "multi_wg_compaction/multi_wg_compaction1_conflict_free.comp.glsl",
+            include_str!(concat!(
+                env!("CARGO_MANIFEST_DIR"),
+                "/shaders/multi_wg_compaction/multi_wg_compaction1_conflict_free.comp.glsl"
+            )),
+        );
+    }
 
+    // `program1` is multi_wg_compaction1 or a drop-in replacement for it
+    fn check_multiple_wg_compaction(program1_name: &str, program1_source: &str) {
         // let N be the number of elements in the input array
         const N: usize = 131_072;
         // let B be the number of elements processed in a block
@@ -524,39 +384,30 @@ mod tests {
         // See https://developer.nvidia.com/gpugems/gpugems3/part-vi-gpu-computing/chapter-39-parallel-prefix-sum-scan-cuda
         // 39.2.4 Arrays of Arbitrary Size
 
-        #[derive(Debug, Copy, Clone)]
-        #[repr(C, packed)]
-        struct InputData {
-            data: [GLuint; N],
-        }
-
-        #[derive(Debug, Copy, Clone)]
-        #[repr(C, packed)]
-        struct OutputData {
-            sums: [GLuint; N_OVER_B],
-            offsets: [GLuint; N],
-            results: [GLuint; N],
-            data: [GLuint; N],
-        }
+        // The output block is laid out as
+        //     uint sums[N_OVER_B];
+        //     uint offsets[N];
+        //     uint results[N];
+        //     uint data[N];
+        const SUMS: std::ops::Range<usize> = 0..N_OVER_B;
+        const OFFSETS: std::ops::Range<usize> = N_OVER_B..N_OVER_B + N;
+        const RESULTS: std::ops::Range<usize> = N_OVER_B + N..N_OVER_B + 2 * N;
+        const DATA: std::ops::Range<usize> = N_OVER_B + 2 * N..N_OVER_B + 3 * N;
+        const OUTPUT_LEN: usize = N_OVER_B + 3 * N;
 
         // *************************************************************************
         // Create OpenGL Context
-        let _window = make_opengl_window();
+        let _context = Context::new().unwrap();
 
         // *************************************************************************
         // Load shader and create program
 
-        let mut substs = std::collections::HashMap::new();
-        substs.insert("N", N);
-        substs.insert("B", B);
-        let program1 = make_compute_shader_program(
-            include_str!(concat!(
-                env!("CARGO_MANIFEST_DIR"),
-                "/shaders/multi_wg_compaction/multi_wg_compaction1.comp.glsl"
-            )),
-            &substs,
-        );
+        let mut substs: HashMap<&str, Param> = HashMap::new();
+        substs.insert("N", N.into());
+        substs.insert("B", B.into());
+        let program1 = make_compute_shader_program(program1_name, program1_source, &substs);
         let program2 = make_compute_shader_program(
+            "multi_wg_compaction/multi_wg_compaction2.comp.glsl",
             include_str!(concat!(
                 env!("CARGO_MANIFEST_DIR"),
                 "/shaders/multi_wg_compaction/multi_wg_compaction2.comp.glsl"
@@ -566,20 +417,13 @@ mod tests {
 
         // *************************************************************************
         // Create random data
-        let data = {
-            let mut arr = [0; N];
-            for i in 0..N {
-                arr[i] = i as GLuint + 1;
-            }
-            arr
-        };
-        let input_data = InputData { data };
+        let data: Vec<GLuint> = (0..N).map(|i| i as GLuint + 1).collect();
 
         // *************************************************************************
         // Calculate expected result
 
         fn prefix_sum(data: Vec<GLuint>) -> Vec<GLuint> {
-            let mut v = data.clone();
+            let mut v = data;
             v.insert(0, 0);
             for i in 1..v.len() {
                 v[i] += v[i - 1];
@@ -588,90 +432,82 @@ mod tests {
             v
         }
 
-        let expected_offsets = prefix_sum(
-            data.to_vec()
-                .iter()
-                .map(|n| (n % 2 == 0) as GLuint)
-                .collect::<Vec<GLuint>>(),
-        );
+        let expected_results: Vec<GLuint> = data.iter().map(|n| (n % 2 == 0) as GLuint).collect();
+        let expected_offsets = prefix_sum(expected_results.clone());
 
         // *************************************************************************
         // Create input and output SSBOs
-        let input_ssbo = make_input_ssbo(&input_data);
-        let output_ssbo = make_output_ssbo::<OutputData>();
+        let input_ssbo = Buffer::from_slice(&data);
+        let mut output_ssbo = Buffer::<GLuint>::new(OUTPUT_LEN);
+        input_ssbo.bind(0);
+        output_ssbo.bind(1);
 
         // *************************************************************************
         // Run compute shader
-        program1.use_();
-        unsafe {
-            gl::DispatchCompute(N_OVER_B as GLuint, 1, 1);
-            gl::MemoryBarrier(gl::BUFFER_UPDATE_BARRIER_BIT);
-        };
+        program1.dispatch(N_OVER_B as GLuint, 1, 1);
 
-        fn inplace_exclusive_prefix_sum(a: &mut [GLuint; N_OVER_B]) {
+        fn inplace_exclusive_prefix_sum(a: &mut [GLuint]) {
             let mut v = a.to_vec();
             v.insert(0, 0);
             for i in 1..v.len() {
                 v[i] += v[i - 1];
             }
             v.pop();
-            for i in 0..v.len() {
-                a[i] = v[i];
-            }
+            a.copy_from_slice(&v);
         }
 
         // TODO(Andrea): should this be a GPU kernel to avoid moving memory?
-        unsafe {
-            gl::BindBuffer(gl::SHADER_STORAGE_BUFFER, output_ssbo);
-
-            let ptr = gl::MapBuffer(gl::SHADER_STORAGE_BUFFER, gl::READ_ONLY) as *mut OutputData;
-
-            inplace_exclusive_prefix_sum(&mut ((*ptr).sums));
-
-            gl::UnmapBuffer(gl::SHADER_STORAGE_BUFFER);
-            gl::BindBuffer(gl::SHADER_STORAGE_BUFFER, 0);
-        }
+        let mut sums = output_ssbo.read(SUMS);
+        inplace_exclusive_prefix_sum(&mut sums);
+        output_ssbo.write(SUMS.start, &sums);
 
-        program2.use_();
-        unsafe {
-            gl::DispatchCompute(N_OVER_B as GLuint, 1, 1);
-            gl::MemoryBarrier(gl::BUFFER_UPDATE_BARRIER_BIT);
-        };
+        program2.dispatch(N_OVER_B as GLuint, 1, 1);
 
         // *************************************************************************
         // Check expected result matches with output
-        let output_struct = get_ssbo::<OutputData>(output_ssbo);
-
-        let offsets = output_struct.offsets;
+        let offsets = output_ssbo.read(OFFSETS);
+        let results = output_ssbo.read(RESULTS);
 
         assert_eq!(
-            offsets.to_vec(),
-            expected_offsets,
+            offsets, expected_offsets,
             "The resulting offsets should match"
         );
-
-        let computed_len = {
-            let mut i = N - 1;
-            loop {
-                if output_struct.results[i] == 1 {
-                    break output_struct.offsets[i] + 1;
-                }
-                if i > 0 {
-                    i -= 1;
-                } else {
-                    break 0;
-                }
-            }
-        };
-        assert_eq!(N / 2, computed_len as usize);
-
-        // *************************************************************************
-        // Cleanup
-        unsafe { gl::DeleteBuffers(1, &input_ssbo) };
+        assert_eq!(results, expected_results);
+
+        // The length comes from the counter of `Compact`, which must keep the
+        // same elements
+        let compacted = Compact::<GLuint>::new("x % 2 == 0")
+            .unwrap()
+            .run(&input_ssbo);
+        assert_eq!(compacted.len(), N / 2);
+        let kept = output_ssbo.read(DATA.start..DATA.start + compacted.len());
+        assert_eq!(kept, compacted.to_vec());
     }
 
     #[test]
     fn test_multiple_wg_raycasting() {
+        check_multiple_wg_raycasting(
+            "multi_wg_raycasting/multi_wg_raycasting1.comp.glsl",
+            include_str!(concat!(
+                env!("CARGO_MANIFEST_DIR"),
+                "/shaders/multi_wg_raycasting/multi_wg_raycasting1.comp.glsl"
+            )),
+        );
+    }
+
+    #[test]
+    fn test_multiple_wg_raycasting_conflict_free() {
+        check_multiple_wg_raycasting(
+            "multi_wg_raycasting/multi_wg_raycasting1_conflict_free.comp.glsl",
+            include_str!(concat!(
+                env!("CARGO_MANIFEST_DIR"),
+                "/shaders/multi_wg_raycasting/multi_wg_raycasting1_conflict_free.comp.glsl"
+            )),
+        );
+    }
+
+    // `program1` is multi_wg_raycasting1 or a drop-in replacement for it
+    fn check_multiple_wg_raycasting(program1_name: &str, program1_source: &str) {
         const CHUNK_X: usize = 7;
         const CHUNK_Y: usize = 7;
         const CHUNK_Z: usize = 7;
@@ -679,60 +515,60 @@ mod tests {
         // let N be the number of rays
         const N: usize = 8;
         // let B be the number of rays processed in a work group
-        const B: usize = 2;
+        const B: usize = 4;
         // then we need to allocate N/B work groups of B/2 invocations each (since
         // each invocation processes two elements)
         const N_OVER_B: usize = N / B;
-        // FIXME: be careful about alignment!!!
-        // Otherwise C, packed and GLSL std430 are not equal
-        assert!(N_OVER_B % 4 == 0, "N_OVER_B must be a multiple of 4");
 
-        #[derive(Debug, Copy, Clone)]
-        #[repr(C, packed)]
+        #[derive(Debug, Copy, Clone, Std430)]
         struct InputData {
-            chunk: [GLuint; CHUNK_SIZE],
-            ray_start: GLuvec4,
+            chunk: [[[GLuint; CHUNK_X]; CHUNK_Y]; CHUNK_Z],
+            ray_start: UVec4,
         }
 
-        #[derive(Debug, Copy, Clone)]
-        #[repr(C, packed)]
+        #[derive(Debug, Copy, Clone, Std430)]
         struct OutputData {
             sums: [GLuint; N_OVER_B],
             offsets: [GLuint; N],
             has_hit: [GLuint; N],
-            hits: [GLuvec4; N],
-            compact_hits: [GLuvec4; N],
+            hits: [UVec4; N],
+            compact_hits: [UVec4; N],
         }
 
         // *************************************************************************
         // Create OpenGL Context
-        let _window = make_opengl_window();
+        let _context = Context::new().unwrap();
 
         // *************************************************************************
         // Load shader and create program
 
-        let mut substs = std::collections::HashMap::new();
-        substs.insert("CHUNK_X", CHUNK_X);
-        substs.insert("CHUNK_Y", CHUNK_Y);
-        substs.insert("CHUNK_Z", CHUNK_Z);
-        substs.insert("CHUNK_SIZE", CHUNK_SIZE);
-        substs.insert("N", N);
-        substs.insert("B", B);
-        substs.insert("N_OVER_B", N_OVER_B);
-        let program1 = make_compute_shader_program(
-            include_str!(concat!(
-                env!("CARGO_MANIFEST_DIR"),
-                "/shaders/multi_wg_raycasting/multi_wg_raycasting1.comp.glsl"
-            )),
-            &substs,
-        );
+        let mut substs: HashMap<&str, Param> = HashMap::new();
+        substs.insert("CHUNK_X", CHUNK_X.into());
+        substs.insert("CHUNK_Y", CHUNK_Y.into());
+        substs.insert("CHUNK_Z", CHUNK_Z.into());
+        substs.insert("CHUNK_SIZE", CHUNK_SIZE.into());
+        substs.insert("N", N.into());
+        substs.insert("B", B.into());
+        let program1 = make_compute_shader_program(program1_name, program1_source, &substs);
         let program2 = make_compute_shader_program(
+            "multi_wg_raycasting/multi_wg_raycasting2.comp.glsl",
             include_str!(concat!(
                 env!("CARGO_MANIFEST_DIR"),
                 "/shaders/multi_wg_raycasting/multi_wg_raycasting2.comp.glsl"
             )),
             &substs,
         );
+        // Fail before dispatching if the Rust structs don't match the blocks
+        for program in [&program1, &program2] {
+            for block in program.buffer_blocks() {
+                match block.name.as_str() {
+                    "InputData" => block.check_layout::<InputData>().unwrap(),
+                    "OutputData" => block.check_layout::<OutputData>().unwrap(),
+                    "Counter" => block.check_layout::<CompactCounter>().unwrap(),
+                    _ => unreachable!(),
+                }
+            }
+        }
 
         // *************************************************************************
         // Create random data
@@ -807,8 +643,8 @@ mod tests {
             ],
         ];
         let input_data = InputData {
-            chunk: unsafe { std::mem::transmute(chunk) },
-            ray_start: [0, 0, 0, 0],
+            chunk,
+            ray_start: UVec4([0, 0, 0, 0]),
         };
 
         // TODO(Andrea): make of a better example chunk  and make all rays have
@@ -819,82 +655,63 @@ mod tests {
 
         // *************************************************************************
         // Create input and output SSBOs
-        let input_ssbo = make_input_ssbo(&input_data);
-        let output_ssbo = make_output_ssbo::<OutputData>();
+        let input_ssbo = Buffer::from_slice(&[input_data]);
+        let mut output_ssbo = Buffer::<OutputData>::new(1);
+        let counter = Buffer::<CompactCounter>::new(1);
+        input_ssbo.bind(0);
+        output_ssbo.bind(1);
+        counter.bind(2);
 
         // *************************************************************************
         // Run compute shader
-        program1.use_();
-        unsafe {
-            gl::DispatchCompute(N_OVER_B as GLuint, 1, 1);
-            gl::MemoryBarrier(gl::BUFFER_UPDATE_BARRIER_BIT);
-        };
+        program1.dispatch(N_OVER_B as GLuint, 1, 1);
 
-        fn inplace_exclusive_prefix_sum(a: &mut [GLuint; N_OVER_B]) {
+        fn inplace_exclusive_prefix_sum(a: &mut [GLuint]) {
             let mut v = a.to_vec();
             v.insert(0, 0);
             for i in 1..v.len() {
                 v[i] += v[i - 1];
             }
             v.pop();
-            for i in 0..v.len() {
-                a[i] = v[i];
-            }
+            a.copy_from_slice(&v);
         }
 
         // TODO(Andrea): should this be a GPU kernel to avoid moving memory?
-        unsafe {
-            gl::BindBuffer(gl::SHADER_STORAGE_BUFFER, output_ssbo);
-
-            let ptr = gl::MapBuffer(gl::SHADER_STORAGE_BUFFER, gl::READ_ONLY) as *mut OutputData;
-
-            inplace_exclusive_prefix_sum(&mut ((*ptr).sums));
+        let mut output_struct = output_ssbo.to_vec()[0];
+        inplace_exclusive_prefix_sum(&mut output_struct.sums);
+        output_ssbo.write(0, &[output_struct]);
 
-            gl::UnmapBuffer(gl::SHADER_STORAGE_BUFFER);
-            gl::BindBuffer(gl::SHADER_STORAGE_BUFFER, 0);
-        }
-
-        program2.use_();
-        unsafe {
-            gl::DispatchCompute(N_OVER_B as GLuint, 1, 1);
-            gl::MemoryBarrier(gl::BUFFER_UPDATE_BARRIER_BIT);
-        };
+        program2.dispatch(N_OVER_B as GLuint, 1, 1);
 
         // *************************************************************************
         // Check expected result matches with output
-        let output_struct = get_ssbo::<OutputData>(output_ssbo);
-        let compact_hits = output_struct.compact_hits;
+        let output_struct = output_ssbo.to_vec()[0];
         assert_eq!(
-            compact_hits,
+            output_struct.compact_hits,
             [
-                [1, 6, 0, 1337],
-                [3, 6, 0, 1337],
-                [3, 6, 0, 1337],
-                [5, 6, 0, 1337],
-                [0, 0, 0, 0],
-                [0, 0, 0, 0],
-                [0, 0, 0, 0],
-                [0, 0, 0, 0]
+                UVec4([1, 6, 0, 1337]),
+                UVec4([3, 6, 0, 1337]),
+                UVec4([3, 6, 0, 1337]),
+                UVec4([5, 6, 0, 1337]),
+                UVec4([0, 0, 0, 0]),
+                UVec4([0, 0, 0, 0]),
+                UVec4([0, 0, 0, 0]),
+                UVec4([0, 0, 0, 0])
             ]
         );
 
-        let computed_len = {
-            let mut i = N - 1;
-            loop {
-                if output_struct.has_hit[i] == 1 {
-                    break output_struct.offsets[i] + 1;
-                }
-                if i > 0 {
-                    i -= 1;
-                } else {
-                    break 0;
-                }
-            }
-        };
-        assert_eq!(4, computed_len as usize);
+        let len = counter.to_vec()[0].len as usize;
+        assert_eq!(len, 4);
 
-        // *************************************************************************
-        // Cleanup
-        unsafe { gl::DeleteBuffers(1, &input_ssbo) };
+        // Two rays hit the same voxel
+        let hits = Buffer::from_slice(&output_struct.compact_hits[..len]);
+        assert_eq!(
+            unique(&hits).unwrap().to_vec(),
+            [
+                UVec4([1, 6, 0, 1337]),
+                UVec4([3, 6, 0, 1337]),
+                UVec4([5, 6, 0, 1337]),
+            ]
+        );
     }
 }
@@ -0,0 +1,340 @@
+use gl::types::*;
+use std::collections::VecDeque;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+/// Times named scopes of GL commands on the GPU, and on the CPU for comparison,
+/// e.g. a dispatch and the host-side fix-up step after it.
+///
+/// Results are read back only once the GPU has them, so profiling does not
+/// stall the pipeline; `wait` is for when every scope must be counted.
+pub struct Profiler {
+    /// The names and queries of the scopes that were begun but not ended.
+    open: Vec<(String, Instant, ScopeQuery)>,
+    /// Ended scopes whose results may not be available yet, oldest first.
+    pending: VecDeque<(String, Duration, ScopeQuery)>,
+    /// GPU and CPU durations of every finished scope, by name in order of first
+    /// appearance.
+    samples: Vec<(String, Vec<(Duration, Duration)>)>,
+    free_elapsed_queries: Vec<GLuint>,
+    free_timestamp_queries: Vec<GLuint>,
+}
+
+// Only one GL_TIME_ELAPSED query can be active at a time, so the outermost
+// scope uses one and the scopes nested in it use a pair of GL_TIMESTAMPs
+enum ScopeQuery {
+    Elapsed(GLuint),
+    Timestamps(GLuint, GLuint),
+}
+
+impl ScopeQuery {
+    /// The query that completes last.
+    fn last(&self) -> GLuint {
+        match *self {
+            ScopeQuery::Elapsed(query) => query,
+            ScopeQuery::Timestamps(_, end) => end,
+        }
+    }
+}
+
+impl Profiler {
+    pub fn new() -> Self {
+        Profiler {
+            open: Vec::new(),
+            pending: VecDeque::new(),
+            samples: Vec::new(),
+            free_elapsed_queries: Vec::new(),
+            free_timestamp_queries: Vec::new(),
+        }
+    }
+
+    /// Starts timing the commands issued from now on as `name`. Scopes can be
+    /// nested, and are closed by `end` in reverse order.
+    pub fn begin(&mut self, name: &str) {
+        let query = if self.open.is_empty() {
+            let query = Self::query(&mut self.free_elapsed_queries, gl::TIME_ELAPSED);
+            unsafe { gl::BeginQuery(gl::TIME_ELAPSED, query) };
+            ScopeQuery::Elapsed(query)
+        } else {
+            let start = Self::query(&mut self.free_timestamp_queries, gl::TIMESTAMP);
+            unsafe { gl::QueryCounter(start, gl::TIMESTAMP) };
+            ScopeQuery::Timestamps(start, 0)
+        };
+        self.open.push((name.to_string(), Instant::now(), query));
+    }
+
+    /// Ends the innermost open scope.
+    pub fn end(&mut self) {
+        let (name, cpu_start, query) = self.open.pop().expect("no open profiler scope");
+        let query = match query {
+            ScopeQuery::Elapsed(query) => {
+                unsafe { gl::EndQuery(gl::TIME_ELAPSED) };
+                ScopeQuery::Elapsed(query)
+            }
+            ScopeQuery::Timestamps(start, _) => {
+                let end = Self::query(&mut self.free_timestamp_queries, gl::TIMESTAMP);
+                unsafe { gl::QueryCounter(end, gl::TIMESTAMP) };
+                ScopeQuery::Timestamps(start, end)
+            }
+        };
+        self.pending.push_back((name, cpu_start.elapsed(), query));
+    }
+
+    /// Times the commands issued by `f` as `name`.
+    pub fn scope<R>(&mut self, name: &str, f: impl FnOnce() -> R) -> R {
+        self.begin(name);
+        let result = f();
+        self.end();
+        result
+    }
+
+    /// Reads back the results the GPU already has, without waiting.
+    pub fn collect(&mut self) {
+        while let Some((_, _, query)) = self.pending.front() {
+            let mut available = 0;
+            unsafe {
+                gl::GetQueryObjectiv(query.last(), gl::QUERY_RESULT_AVAILABLE, &mut available)
+            };
+            if available == 0 {
+                // Queries complete in order, so the later ones are not ready
+                // either
+                break;
+            }
+            self.finish_front();
+        }
+    }
+
+    /// Waits for the results of every ended scope.
+    pub fn wait(&mut self) {
+        while !self.pending.is_empty() {
+            self.finish_front();
+        }
+    }
+
+    /// Forgets the timings collected so far, e.g. at the start of a frame.
+    pub fn reset(&mut self) {
+        self.samples.clear();
+    }
+
+    /// Collects the available results and summarizes all the timings since
+    /// the last `reset`.
+    pub fn report(&mut self) -> Report {
+        self.collect();
+        Report {
+            scopes: self
+                .samples
+                .iter()
+                .map(|(name, samples)| ScopeTimings {
+                    name: name.clone(),
+                    samples: samples.len(),
+                    gpu: Stats::new(samples.iter().map(|&(gpu, _)| gpu)),
+                    cpu: Stats::new(samples.iter().map(|&(_, cpu)| cpu)),
+                })
+                .collect(),
+        }
+    }
+
+    fn finish_front(&mut self) {
+        let (name, cpu, query) = self.pending.pop_front().unwrap();
+        let gpu = match query {
+            ScopeQuery::Elapsed(query) => {
+                let elapsed = Self::result(query);
+                self.free_elapsed_queries.push(query);
+                elapsed
+            }
+            ScopeQuery::Timestamps(start, end) => {
+                let elapsed = Self::result(end).saturating_sub(Self::result(start));
+                self.free_timestamp_queries.extend([start, end]);
+                elapsed
+            }
+        };
+
+        let gpu = Duration::from_nanos(gpu);
+        match self.samples.iter_mut().find(|(scope, _)| *scope == name) {
+            Some((_, samples)) => samples.push((gpu, cpu)),
+            None => self.samples.push((name, vec![(gpu, cpu)])),
+        }
+    }
+
+    fn query(free: &mut Vec<GLuint>, target: GLenum) -> GLuint {
+        free.pop().unwrap_or_else(|| {
+            let mut query = 0;
+            unsafe { gl::CreateQueries(target, 1, &mut query) };
+            query
+        })
+    }
+
+    /// Blocks until the result of `query` is available, in nanoseconds.
+    fn result(query: GLuint) -> u64 {
+        let mut result = 0;
+        unsafe { gl::GetQueryObjectui64v(query, gl::QUERY_RESULT, &mut result) };
+        result
+    }
+}
+
+impl Default for Profiler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for Profiler {
+    fn drop(&mut self) {
+        let mut queries: Vec<GLuint> = self
+            .free_elapsed_queries
+            .drain(..)
+            .chain(self.free_timestamp_queries.drain(..))
+            .collect();
+        let open = self.open.drain(..).map(|(_, _, query)| query);
+        for query in open.chain(self.pending.drain(..).map(|(_, _, query)| query)) {
+            match query {
+                ScopeQuery::Elapsed(query) => queries.push(query),
+                ScopeQuery::Timestamps(start, end) => queries.extend([start, end]),
+            }
+        }
+        // Zeros, for scopes that were never ended, are silently ignored
+        unsafe { gl::DeleteQueries(queries.len() as GLsizei, queries.as_ptr()) };
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Stats {
+    pub min: Duration,
+    pub mean: Duration,
+    pub max: Duration,
+}
+
+impl Stats {
+    fn new(durations: impl Iterator<Item = Duration> + Clone) -> Self {
+        let count = durations.clone().count() as u32;
+        Stats {
+            min: durations.clone().min().unwrap_or_default(),
+            mean: durations.clone().sum::<Duration>() / count.max(1),
+            max: durations.max().unwrap_or_default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScopeTimings {
+    pub name: String,
+    pub samples: usize,
+    pub gpu: Stats,
+    pub cpu: Stats,
+}
+
+/// The timings of each scope, printed as a table.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Report {
+    pub scopes: Vec<ScopeTimings>,
+}
+
+impl Report {
+    pub fn scope(&self, name: &str) -> Option<&ScopeTimings> {
+        self.scopes.iter().find(|scope| scope.name == name)
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let width = self
+            .scopes
+            .iter()
+            .map(|scope| scope.name.len())
+            .max()
+            .unwrap_or(0)
+            .max("scope".len());
+        let ms = |duration: Duration| duration.as_secs_f64() * 1e3;
+
+        write!(
+            f,
+            "{:<width$} {:>7}  {:>28}  {:>28}",
+            "scope",
+            "samples",
+            "gpu min/mean/max (ms)",
+            "cpu min/mean/max (ms)",
+            width = width
+        )?;
+        for scope in &self.scopes {
+            write!(
+                f,
+                "\n{:<width$} {:>7}  {:>8.3} {:>9.3} {:>9.3}  {:>8.3} {:>9.3} {:>9.3}",
+                scope.name,
+                scope.samples,
+                ms(scope.gpu.min),
+                ms(scope.gpu.mean),
+                ms(scope.gpu.max),
+                ms(scope.cpu.min),
+                ms(scope.cpu.mean),
+                ms(scope.cpu.max),
+                width = width
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Profiler;
+    use crate::template::{Param, ShaderTemplate};
+    use crate::{Buffer, Context, Program};
+    use std::collections::HashMap;
+    use std::time::Duration;
+
+    const SOURCE: &str = "
+#version 450 core
+layout(local_size_x = 64, local_size_y = 1, local_size_z = 1) in;
+
+layout(std430, binding = 0) buffer Data {
+    float data[];
+};
+
+void main() {
+    float x = data[gl_GlobalInvocationID.x];
+    for (int i = 0; i < 1000; i++) {
+        x = sin(x) + 1.0;
+    }
+    data[gl_GlobalInvocationID.x] = x;
+}
+";
+
+    #[test]
+    fn test_profiler() {
+        let _context = Context::new().unwrap();
+        let template = ShaderTemplate::parse("profiler.comp.glsl", SOURCE).unwrap();
+        let kernel = template
+            .compile(&HashMap::<&str, Param>::new(), gl::COMPUTE_SHADER)
+            .unwrap();
+        let program = Program::new(vec![(kernel, gl::COMPUTE_SHADER)]).unwrap();
+        let buffer = Buffer::from_slice(&vec![0.0f32; 64 * 64]);
+        buffer.bind(0);
+
+        let mut profiler = Profiler::new();
+        for _ in 0..3 {
+            profiler.begin("frame");
+            profiler.scope("kernel", || program.dispatch(64, 1, 1));
+            profiler.scope("read back", || buffer.to_vec());
+            profiler.end();
+        }
+        profiler.wait();
+
+        let report = profiler.report();
+        assert_eq!(
+            report
+                .scopes
+                .iter()
+                .map(|scope| (scope.name.as_str(), scope.samples))
+                .collect::<Vec<_>>(),
+            vec![("kernel", 3), ("read back", 3), ("frame", 3)]
+        );
+        let frame = report.scope("frame").unwrap();
+        assert!(frame.gpu.min > Duration::from_nanos(0));
+        assert!(frame.gpu.min <= frame.gpu.mean && frame.gpu.mean <= frame.gpu.max);
+        assert!(frame.cpu.min <= frame.cpu.mean && frame.cpu.mean <= frame.cpu.max);
+        assert_eq!(report.to_string().lines().count(), 4);
+
+        profiler.reset();
+        assert_eq!(profiler.report().scopes, vec![]);
+    }
+}
@@ -1,16 +1,32 @@
 use gl::types::*;
 
+use crate::barrier::{self, BufferAccess};
+use crate::debug_message_callback;
+use crate::error::{Diagnostic, ShaderError};
 use crate::shader::Shader;
+use crate::{Buffer, Std430};
 use std::ffi::CString;
+use std::marker::PhantomData;
 
 pub struct Program {
     id: GLuint,
+    /// The binding points of the program's `buffer` blocks.
+    storage_bindings: Vec<GLuint>,
+    /// Neither `Send` nor `Sync`, like `Buffer`.
+    _not_send: PhantomData<*const ()>,
 }
 
 impl Program {
-    pub fn new(shaders: Vec<(Shader, GLenum)>) -> Result<Self, String> {
+    pub fn new(shaders: Vec<(Shader, GLenum)>) -> Result<Self, ShaderError> {
         let program = unsafe { gl::CreateProgram() };
         unsafe {
+            // Lets `binary` work on drivers that only keep the binary around
+            // when asked to
+            gl::ProgramParameteri(
+                program,
+                gl::PROGRAM_BINARY_RETRIEVABLE_HINT,
+                gl::TRUE as GLint,
+            );
             for (shader, _) in &shaders {
                 gl::AttachShader(program, shader.id());
             }
@@ -20,6 +36,25 @@ impl Program {
                 gl::DetachShader(program, shader.id());
             }
         }
+        Self::check_link_status(program)
+    }
+
+    /// Loads a program returned by `binary`. The driver rejects binaries from
+    /// other drivers or versions, which is reported as a link error.
+    pub fn from_binary(format: GLenum, binary: &[u8]) -> Result<Self, ShaderError> {
+        let program = unsafe { gl::CreateProgram() };
+        unsafe {
+            gl::ProgramBinary(
+                program,
+                format,
+                binary.as_ptr() as *const GLvoid,
+                binary.len() as GLsizei,
+            );
+        }
+        Self::check_link_status(program)
+    }
+
+    fn check_link_status(program: GLuint) -> Result<Self, ShaderError> {
         let mut success: GLint = 1;
         unsafe {
             gl::GetProgramiv(program, gl::LINK_STATUS, &mut success);
@@ -39,13 +74,81 @@ impl Program {
                     std::ptr::null_mut(),
                     error.as_ptr() as *mut gl::types::GLchar,
                 );
+                gl::DeleteProgram(program);
             }
 
-            return Err(error.to_string_lossy().to_string());
+            return Err(ShaderError::Link {
+                diagnostics: Diagnostic::parse_info_log(&error.to_string_lossy()),
+            });
+        }
+
+        let mut program = Program {
+            id: program,
+            storage_bindings: Vec::new(),
+            _not_send: PhantomData,
+        };
+        program.storage_bindings = program
+            .buffer_blocks()
+            .iter()
+            .map(|block| block.binding)
+            .collect();
+        Ok(program)
+    }
+
+    /// The driver specific binary of the linked program and its format, for
+    /// `from_binary`. `None` if the driver does not support program binaries.
+    pub fn binary(&self) -> Option<(GLenum, Vec<u8>)> {
+        let mut len: GLint = 0;
+        unsafe { gl::GetProgramiv(self.id, gl::PROGRAM_BINARY_LENGTH, &mut len) };
+        if len == 0 {
+            return None;
         }
 
-        Ok(Program { id: program })
+        let mut binary = vec![0u8; len as usize];
+        let mut written = 0;
+        let mut format = 0;
+        unsafe {
+            gl::GetProgramBinary(
+                self.id,
+                len,
+                &mut written,
+                &mut format,
+                binary.as_mut_ptr() as *mut GLvoid,
+            );
+        }
+        binary.truncate(written as usize);
+        Some((format, binary))
+    }
+
+    /// Runs the compute shader on a grid of work groups, with whatever buffers
+    /// are bound. Waits for earlier dispatches that wrote to those buffers
+    /// first, and later `Buffer` reads and writes wait for this one.
+    pub fn dispatch(&self, num_groups_x: GLuint, num_groups_y: GLuint, num_groups_z: GLuint) {
+        self.use_();
+        barrier::dispatch(&self.storage_bindings);
+        unsafe { gl::DispatchCompute(num_groups_x, num_groups_y, num_groups_z) };
+        debug_message_callback::check();
     }
+
+    /// Like `dispatch`, with the number of work groups read on the GPU from the
+    /// three `GLuint`s at byte `offset` in `buffer`, e.g. written by an
+    /// earlier dispatch.
+    pub fn dispatch_indirect<T: Std430>(&self, buffer: &Buffer<T>, offset: usize) {
+        assert!(
+            offset.is_multiple_of(4) && offset + 12 <= buffer.len() * T::STRIDE,
+            "the dispatch parameters at {} are out of bounds or unaligned",
+            offset
+        );
+        self.use_();
+        buffer.prepare(BufferAccess::Command);
+        barrier::dispatch(&self.storage_bindings);
+        unsafe {
+            gl::BindBuffer(gl::DISPATCH_INDIRECT_BUFFER, buffer.id());
+            gl::DispatchComputeIndirect(offset as GLintptr);
+        }
+        debug_message_callback::check();
+    }
+
     pub fn use_(&self) {
         unsafe { gl::UseProgram(self.get_id()) };
     }
@@ -0,0 +1,240 @@
+use gl::types::*;
+use std::collections::HashMap;
+
+use crate::error::ShaderError;
+use crate::scan::{kernel, Scan, ScanKind, ScanOp};
+use crate::template::Param;
+use crate::{Buffer, Program, Std430};
+
+// Must match BITS in radix_sort_histogram and radix_sort_scatter
+const BITS: u32 = 4;
+const RADIX: usize = 1 << BITS;
+
+/// A 32 bit key `RadixSort` can sort. Its bits are turned into ones that sort
+/// like unsigned integers on the GPU: ints have their sign bit flipped, and
+/// floats also have the other bits of negative numbers flipped.
+pub trait RadixKey: Std430 {
+    /// The value of `key_kind` in the radix sort kernels.
+    #[doc(hidden)]
+    const KIND: GLuint;
+}
+
+impl RadixKey for u32 {
+    const KIND: GLuint = 0;
+}
+
+impl RadixKey for i32 {
+    const KIND: GLuint = 1;
+}
+
+/// NaNs with the sign bit set come first, the others last.
+impl RadixKey for f32 {
+    const KIND: GLuint = 2;
+}
+
+/// Least significant digit radix sort, as in CS344: every pass sorts the keys
+/// by one `BITS` digit, counting the digits of each work group in
+/// `radix_sort_histogram`, scanning the counts with `Scan`, and moving the keys
+/// to the scanned offsets in `radix_sort_scatter`. Every pass is stable, so
+/// the keys end up sorted by all digits.
+pub struct RadixSort {
+    histogram: Program,
+    scan: Scan<GLuint>,
+    scatter: Program,
+}
+
+impl RadixSort {
+    pub fn new() -> Result<Self, ShaderError> {
+        let no_substs: HashMap<&str, Param> = HashMap::new();
+        Ok(RadixSort {
+            histogram: kernel(
+                "radix_sort/radix_sort_histogram.comp.glsl",
+                include_str!("../shaders/radix_sort/radix_sort_histogram.comp.glsl"),
+                &no_substs,
+            )?,
+            scan: Scan::new(ScanOp::Add)?,
+            scatter: kernel(
+                "radix_sort/radix_sort_scatter.comp.glsl",
+                include_str!("../shaders/radix_sort/radix_sort_scatter.comp.glsl"),
+                &no_substs,
+            )?,
+        })
+    }
+
+    /// Returns `keys` sorted in ascending order.
+    pub fn sort<K: RadixKey>(&self, keys: &Buffer<K>) -> Buffer<K> {
+        self.sort_by_key::<K, GLuint>(keys, None).0
+    }
+
+    /// Returns `keys` sorted in ascending order, and the elements of `payload`
+    /// moved along with their keys. Equal keys keep their order.
+    pub fn sort_with_payload<K: RadixKey, P: Std430>(
+        &self,
+        keys: &Buffer<K>,
+        payload: &Buffer<P>,
+    ) -> (Buffer<K>, Buffer<P>) {
+        assert_eq!(
+            keys.len(),
+            payload.len(),
+            "there must be one payload element per key"
+        );
+
+        let (keys, payload) = self.sort_by_key(keys, Some(payload));
+        (keys, payload.unwrap())
+    }
+
+    fn sort_by_key<K: RadixKey, P: Std430>(
+        &self,
+        keys: &Buffer<K>,
+        payload: Option<&Buffer<P>>,
+    ) -> (Buffer<K>, Option<Buffer<P>>) {
+        let len = keys.len();
+        let work_groups = len.div_ceil(self.histogram.work_group_size()[0] as usize);
+        // Strides are multiples of the alignment, which is at least 4
+        let payload_words = payload.map_or(0, |_| P::STRIDE / 4);
+        for program in [&self.histogram, &self.scatter] {
+            unsafe {
+                gl::ProgramUniform1ui(program.get_id(), 0, len as GLuint);
+                gl::ProgramUniform1ui(program.get_id(), 2, K::KIND);
+            }
+        }
+        unsafe { gl::ProgramUniform1ui(self.scatter.get_id(), 3, payload_words as GLuint) };
+
+        // The input is left untouched, the passes go back and forth between
+        // two new buffers
+        let mut sorted = [Buffer::<K>::new(len), Buffer::<K>::new(len)];
+        let mut sorted_payload = payload.map(|payload| {
+            [
+                Buffer::<P>::new(payload.len()),
+                Buffer::<P>::new(payload.len()),
+            ]
+        });
+        if len == 0 {
+            let [keys, _] = sorted;
+            return (keys, sorted_payload.map(|[payload, _]| payload));
+        }
+
+        let counts = Buffer::<GLuint>::new(RADIX * work_groups);
+        for pass in 0..32 / BITS {
+            let shift = pass * BITS;
+            let (from, from_payload) = if pass == 0 {
+                (keys, payload)
+            } else {
+                (
+                    &sorted[0],
+                    sorted_payload.as_ref().map(|[payload, _]| payload),
+                )
+            };
+
+            unsafe { gl::ProgramUniform1ui(self.histogram.get_id(), 1, shift) };
+            from.bind(0);
+            counts.bind(1);
+            self.histogram.dispatch(work_groups as GLuint, 1, 1);
+
+            let offsets = self.scan.run(&counts, ScanKind::Exclusive);
+
+            unsafe { gl::ProgramUniform1ui(self.scatter.get_id(), 1, shift) };
+            from.bind(0);
+            offsets.bind(1);
+            sorted[1].bind(2);
+            if let (Some(from_payload), Some([_, to_payload])) = (from_payload, &sorted_payload) {
+                from_payload.bind(3);
+                to_payload.bind(4);
+            }
+            self.scatter.dispatch(work_groups as GLuint, 1, 1);
+
+            sorted.swap(0, 1);
+            if let Some(sorted_payload) = &mut sorted_payload {
+                sorted_payload.swap(0, 1);
+            }
+        }
+
+        let [keys, _] = sorted;
+        (keys, sorted_payload.map(|[payload, _]| payload))
+    }
+}
+
+/// Sorts `keys` with a one-off `RadixSort`. Keep the `RadixSort` around to sort
+/// many buffers, it compiles its kernels once.
+pub fn radix_sort<K: RadixKey>(keys: &Buffer<K>) -> Result<Buffer<K>, ShaderError> {
+    Ok(RadixSort::new()?.sort(keys))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{radix_sort, RadixSort};
+    use crate::std430::Vec2;
+    use crate::{Buffer, Context};
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    #[test]
+    fn test_radix_sort() {
+        let _context = Context::new().unwrap();
+        let radix_sort = RadixSort::new().unwrap();
+        let mut rng = StdRng::seed_from_u64(21);
+
+        // Many work groups, partial work groups, and few distinct keys
+        for &(len, max) in &[
+            (0, u32::MAX),
+            (1, u32::MAX),
+            (255, u32::MAX),
+            (257, u32::MAX),
+            (10_000, u32::MAX),
+            (100_000, u32::MAX),
+            (10_000, 4),
+        ] {
+            let mut keys: Vec<u32> = (0..len).map(|_| rng.gen_range(0, max)).collect();
+            let sorted = radix_sort.sort(&Buffer::from_slice(&keys));
+
+            keys.sort();
+            assert_eq!(sorted.to_vec(), keys, "len {}", len);
+        }
+    }
+
+    #[test]
+    fn test_radix_sort_signed_and_float_keys() {
+        let _context = Context::new().unwrap();
+        let mut rng = StdRng::seed_from_u64(22);
+
+        let mut ints: Vec<i32> = (0..5000).map(|_| rng.gen()).collect();
+        ints.extend([0, -1, i32::MIN, i32::MAX]);
+        let sorted = radix_sort(&Buffer::from_slice(&ints)).unwrap();
+        ints.sort();
+        assert_eq!(sorted.to_vec(), ints);
+
+        let mut floats: Vec<f32> = (0..5000).map(|_| rng.gen_range(-1e6, 1e6)).collect();
+        floats.extend([
+            0.0,
+            -0.0,
+            f32::INFINITY,
+            f32::NEG_INFINITY,
+            f32::MIN_POSITIVE,
+        ]);
+        let sorted = radix_sort(&Buffer::from_slice(&floats)).unwrap();
+        floats.sort_by(f32::total_cmp);
+        // Compares the bits, to tell -0.0 from 0.0
+        let bits = |floats: &[f32]| floats.iter().map(|x| x.to_bits()).collect::<Vec<_>>();
+        assert_eq!(bits(&sorted.to_vec()), bits(&floats));
+    }
+
+    #[test]
+    fn test_radix_sort_payload() {
+        let _context = Context::new().unwrap();
+        let radix_sort = RadixSort::new().unwrap();
+        let mut rng = StdRng::seed_from_u64(23);
+
+        // Few distinct keys, so that the payload shows whether the sort is stable
+        let keys: Vec<u32> = (0..20_000).map(|_| rng.gen_range(0, 100)).collect();
+        let payload: Vec<Vec2> = (0..20_000).map(|i| Vec2([i as f32, rng.gen()])).collect();
+        let (sorted_keys, sorted_payload) =
+            radix_sort.sort_with_payload(&Buffer::from_slice(&keys), &Buffer::from_slice(&payload));
+
+        let mut expected: Vec<(u32, Vec2)> = keys.into_iter().zip(payload).collect();
+        // `sort_by_key` is stable too
+        expected.sort_by_key(|&(key, _)| key);
+        let (expected_keys, expected_payload): (Vec<u32>, Vec<Vec2>) = expected.into_iter().unzip();
+        assert_eq!(sorted_keys.to_vec(), expected_keys);
+        assert_eq!(sorted_payload.to_vec(), expected_payload);
+    }
+}
@@ -0,0 +1,277 @@
+use gl::types::*;
+use std::marker::PhantomData;
+
+use crate::element::Element;
+use crate::error::ShaderError;
+use crate::scan::{kernel, kernel_substs, ScanOp};
+use crate::{Buffer, Program};
+
+/// Reduces a buffer of any `Element` type and length to the combination of all
+/// of its elements with a `ScanOp`, which is cheaper than scanning it when only
+/// the total is needed.
+///
+/// Every work group of `reduce` combines a block of elements into one partial
+/// in shared memory, and the partials are reduced the same way, on the GPU,
+/// until one is left.
+pub struct Reduce<T: Element> {
+    op: ScanOp,
+    block_len: usize,
+    program: Program,
+    _marker: PhantomData<T>,
+}
+
+impl<T: Element> Reduce<T> {
+    /// The number of elements reduced by each work group, with one thread per
+    /// two elements.
+    pub const DEFAULT_BLOCK_LEN: usize = 1024;
+
+    pub fn new(op: ScanOp) -> Result<Self, ShaderError> {
+        Self::with_block_len(op, Self::DEFAULT_BLOCK_LEN)
+    }
+
+    /// `block_len` must be a power of two, and at most twice the maximum work
+    /// group size.
+    pub fn with_block_len(op: ScanOp, block_len: usize) -> Result<Self, ShaderError> {
+        let (functions, glsl_op, identity) = op.glsl::<T>();
+        let mut substs = kernel_substs::<T>(block_len, functions, glsl_op)?;
+        substs.insert("IDENTITY", identity.into());
+        let program = kernel(
+            "reduction/reduce.comp.glsl",
+            include_str!("../shaders/reduction/reduce.comp.glsl"),
+            &substs,
+        )?;
+
+        Ok(Reduce {
+            op,
+            block_len,
+            program,
+            _marker: PhantomData,
+        })
+    }
+
+    pub fn op(&self) -> &ScanOp {
+        &self.op
+    }
+
+    pub fn block_len(&self) -> usize {
+        self.block_len
+    }
+
+    /// Returns a buffer with the single element `a0 op a1 op ... op an` of
+    /// `input`, the identity of the operator if it is empty.
+    pub fn run(&self, input: &Buffer<T>) -> Buffer<T> {
+        let mut partials = self.reduce_level(input);
+        while partials.len() > 1 {
+            partials = self.reduce_level(&partials);
+        }
+        partials
+    }
+
+    fn reduce_level(&self, input: &Buffer<T>) -> Buffer<T> {
+        let work_groups = input.len().div_ceil(self.block_len).max(1);
+        let partials = Buffer::new(work_groups);
+
+        unsafe { gl::ProgramUniform1ui(self.program.get_id(), 0, input.len() as GLuint) };
+        input.bind(0);
+        partials.bind(1);
+        self.program.dispatch(work_groups as GLuint, 1, 1);
+        partials
+    }
+}
+
+/// Which element `ArgReduce` looks for.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ArgOp {
+    Min,
+    Max,
+}
+
+/// Finds the index of the smallest or largest element of a buffer of scalars,
+/// along with its value, the same way `Reduce` does. Ties go to the first
+/// element.
+pub struct ArgReduce<T: Element> {
+    op: ArgOp,
+    block_len: usize,
+    program: Program,
+    _marker: PhantomData<T>,
+}
+
+impl<T: Element> ArgReduce<T> {
+    pub const DEFAULT_BLOCK_LEN: usize = 1024;
+
+    /// Fails to compile for vector types, which have no ordering.
+    pub fn new(op: ArgOp) -> Result<Self, ShaderError> {
+        Self::with_block_len(op, Self::DEFAULT_BLOCK_LEN)
+    }
+
+    pub fn with_block_len(op: ArgOp, block_len: usize) -> Result<Self, ShaderError> {
+        let (better, worst) = match op {
+            ArgOp::Min => ("a < b", T::MAX),
+            ArgOp::Max => ("a > b", T::MIN),
+        };
+        // The operator is always `pick`, which compares with BETTER
+        let mut substs = kernel_substs::<T>(block_len, "", "")?;
+        substs.remove("FUNCTIONS");
+        substs.remove("OP");
+        substs.insert("BETTER", better.into());
+        substs.insert("WORST", worst.into());
+        let program = kernel(
+            "reduction/arg_reduce.comp.glsl",
+            include_str!("../shaders/reduction/arg_reduce.comp.glsl"),
+            &substs,
+        )?;
+
+        Ok(ArgReduce {
+            op,
+            block_len,
+            program,
+            _marker: PhantomData,
+        })
+    }
+
+    pub fn op(&self) -> ArgOp {
+        self.op
+    }
+
+    pub fn block_len(&self) -> usize {
+        self.block_len
+    }
+
+    /// Returns buffers with the single value and index of the smallest or
+    /// largest element of `input`. If it is empty, the index is `u32::MAX`.
+    pub fn run(&self, input: &Buffer<T>) -> (Buffer<T>, Buffer<GLuint>) {
+        let (mut partials, mut indices) = self.reduce_level(input, None);
+        while partials.len() > 1 {
+            (partials, indices) = self.reduce_level(&partials, Some(&indices));
+        }
+        (partials, indices)
+    }
+
+    fn reduce_level(
+        &self,
+        input: &Buffer<T>,
+        indices: Option<&Buffer<GLuint>>,
+    ) -> (Buffer<T>, Buffer<GLuint>) {
+        let work_groups = input.len().div_ceil(self.block_len).max(1);
+        let partials = Buffer::new(work_groups);
+        let partial_indices = Buffer::new(work_groups);
+
+        let id = self.program.get_id();
+        unsafe {
+            gl::ProgramUniform1ui(id, 0, input.len() as GLuint);
+            gl::ProgramUniform1ui(id, 1, indices.is_some() as GLuint);
+        }
+        input.bind(0);
+        partials.bind(1);
+        if let Some(indices) = indices {
+            indices.bind(2);
+        }
+        partial_indices.bind(3);
+        self.program.dispatch(work_groups as GLuint, 1, 1);
+        (partials, partial_indices)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ArgOp, ArgReduce, Reduce};
+    use crate::std430::IVec2;
+    use crate::{Buffer, Context, ScanOp, ShaderError};
+    use rand::Rng;
+
+    #[test]
+    fn test_reduce() {
+        let _context = Context::new().unwrap();
+        let mut rng = rand::thread_rng();
+        let sum = Reduce::<u32>::with_block_len(ScanOp::Add, 8).unwrap();
+        let min = Reduce::<i32>::new(ScanOp::Min).unwrap();
+        let max = Reduce::<f32>::new(ScanOp::Max).unwrap();
+
+        // Empty, partial blocks, and many levels of partials
+        for &len in &[0, 1, 7, 8, 9, 1000, 100_000] {
+            let input: Vec<u32> = (0..len).map(|_| rng.gen_range(0, 1000)).collect();
+            let total = sum.run(&Buffer::from_slice(&input)).to_vec();
+            assert_eq!(total, vec![input.iter().sum::<u32>()], "len {}", len);
+
+            let input: Vec<i32> = (0..len).map(|_| rng.gen()).collect();
+            let total = min.run(&Buffer::from_slice(&input)).to_vec()[0];
+            assert_eq!(total, input.iter().copied().min().unwrap_or(i32::MAX));
+
+            let input: Vec<f32> = (0..len).map(|_| rng.gen_range(-1e6, 1e6)).collect();
+            let total = max.run(&Buffer::from_slice(&input)).to_vec()[0];
+            assert_eq!(
+                total,
+                input.iter().copied().fold(f32::NEG_INFINITY, f32::max)
+            );
+        }
+    }
+
+    #[test]
+    fn test_reduce_non_commutative() {
+        let _context = Context::new().unwrap();
+
+        // Composes the affine maps x -> x * s + t, stored as (s, t),
+        // which only works if the elements are combined in order
+        let compose = ScanOp::Custom {
+            functions: String::new(),
+            op: "ivec2(a.x * b.x, a.y * b.x + b.y)".to_string(),
+            identity: "ivec2(1, 0)".to_string(),
+        };
+        let reduce = Reduce::<IVec2>::with_block_len(compose, 4).unwrap();
+        let input: Vec<IVec2> = (0..100).map(|i| IVec2([1 + i % 2, i % 5])).collect();
+
+        let expected = input.iter().fold(IVec2([1, 0]), |a, b| {
+            IVec2([
+                a.0[0].wrapping_mul(b.0[0]),
+                a.0[1].wrapping_mul(b.0[0]).wrapping_add(b.0[1]),
+            ])
+        });
+        assert_eq!(
+            reduce.run(&Buffer::from_slice(&input)).to_vec(),
+            vec![expected]
+        );
+    }
+
+    #[test]
+    fn test_arg_reduce() {
+        let _context = Context::new().unwrap();
+        let mut rng = rand::thread_rng();
+        let argmin = ArgReduce::<f32>::with_block_len(ArgOp::Min, 16).unwrap();
+        let argmax = ArgReduce::<u32>::new(ArgOp::Max).unwrap();
+
+        for &len in &[1, 15, 16, 17, 5000, 100_000] {
+            // The nearest hit
+            let distances: Vec<f32> = (0..len).map(|_| rng.gen_range(0.0, 100.0)).collect();
+            let (value, index) = argmin.run(&Buffer::from_slice(&distances));
+            let nearest = (0..len)
+                .min_by(|&a, &b| distances[a].total_cmp(&distances[b]))
+                .unwrap();
+            assert_eq!(value.to_vec(), vec![distances[nearest]]);
+            assert_eq!(index.to_vec(), vec![nearest as u32], "len {}", len);
+
+            // Few distinct values, so that there are ties
+            let input: Vec<u32> = (0..len).map(|_| rng.gen_range(0, 10)).collect();
+            let (value, index) = argmax.run(&Buffer::from_slice(&input));
+            let max = *input.iter().max().unwrap();
+            let first = input.iter().position(|&x| x == max).unwrap();
+            assert_eq!(value.to_vec(), vec![max]);
+            assert_eq!(index.to_vec(), vec![first as u32], "len {}", len);
+        }
+
+        let (_, index) = argmax.run(&Buffer::from_slice(&[]));
+        assert_eq!(index.to_vec(), vec![u32::MAX]);
+    }
+
+    #[test]
+    fn test_arg_reduce_vector_error() {
+        let _context = Context::new().unwrap();
+
+        match ArgReduce::<IVec2>::new(ArgOp::Min) {
+            Err(ShaderError::Compile { shader, .. }) => {
+                assert_eq!(shader, "reduction/arg_reduce.comp.glsl")
+            }
+            Err(err) => panic!("unexpected error: {}", err),
+            Ok(_) => panic!("vectors have no ordering"),
+        }
+    }
+}
@@ -0,0 +1,533 @@
+use gl::types::*;
+
+use crate::program::Program;
+use crate::std430::Layout;
+use crate::Std430;
+
+/// A `buffer` block of a linked program, as reported by the driver.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BufferBlock {
+    pub name: String,
+    pub binding: GLuint,
+    /// The minimum buffer size in bytes. With a runtime-sized array as the
+    /// last member this counts a single element of it.
+    pub size: usize,
+    pub variables: Vec<BufferVariable>,
+}
+
+/// An active variable of a `buffer` block. Arrays of structs are split into
+/// one variable per struct member, like `hits[0].position`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BufferVariable {
+    /// The name relative to the block, without the block name prefix.
+    pub name: String,
+    pub offset: usize,
+    pub array_size: usize,
+    pub array_stride: usize,
+    /// The size of the top-level block member this is part of, 0 if it is a
+    /// runtime-sized array.
+    pub top_level_array_size: usize,
+    pub top_level_array_stride: usize,
+}
+
+/// An active uniform outside of any uniform block.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Uniform {
+    pub name: String,
+    pub location: GLint,
+    /// The GLSL type, e.g. `gl::FLOAT_VEC3`.
+    pub type_: GLenum,
+    pub array_size: usize,
+}
+
+impl BufferBlock {
+    /// The top-level members of the block in declaration order, with their
+    /// byte offsets, like `Std430::FIELDS`.
+    pub fn members(&self) -> Vec<(&str, usize)> {
+        let mut members: Vec<(&str, usize)> = Vec::new();
+        for variable in &self.variables {
+            let end = variable
+                .name
+                .find(['[', '.'])
+                .unwrap_or(variable.name.len());
+            let name = &variable.name[..end];
+            match members.iter_mut().find(|(member, _)| *member == name) {
+                Some((_, offset)) => *offset = (*offset).min(variable.offset),
+                None => members.push((name, variable.offset)),
+            }
+        }
+        members.sort_by_key(|&(_, offset)| offset);
+        members
+    }
+
+    /// Checks that `T` has the same members at the same offsets as the block,
+    /// down to the members of structs in arrays, with the same array strides,
+    /// and that a buffer of one `T` is big enough for it.
+    pub fn check_layout<T: Std430>(&self) -> Result<(), String> {
+        let members = self.members();
+
+        for &(name, offset) in T::FIELDS {
+            match members.iter().find(|(member, _)| *member == name) {
+                Some(&(_, block_offset)) if block_offset != offset => {
+                    return Err(format!(
+                        "{}: member `{}` is at offset {} in the shader but at {} in {}",
+                        self.name,
+                        name,
+                        block_offset,
+                        offset,
+                        std::any::type_name::<T>()
+                    ))
+                }
+                Some(_) => (),
+                None => {
+                    return Err(format!(
+                        "{}: {} has a member `{}` the block does not have",
+                        self.name,
+                        std::any::type_name::<T>(),
+                        name
+                    ))
+                }
+            }
+        }
+        for (name, _) in &members {
+            if !T::FIELDS.iter().any(|(field, _)| field == name) {
+                return Err(format!(
+                    "{}: member `{}` is missing from {}",
+                    self.name,
+                    name,
+                    std::any::type_name::<T>()
+                ));
+            }
+        }
+
+        for variable in &self.variables {
+            let (offset, top_level_array_stride, array_stride) =
+                match locate(&T::LAYOUT, &variable.name) {
+                    Some(location) => location,
+                    None => {
+                        return Err(format!(
+                            "{}: `{}` is nested differently in {}",
+                            self.name,
+                            variable.name,
+                            std::any::type_name::<T>()
+                        ))
+                    }
+                };
+            let mismatch = if variable.offset != offset {
+                Some(("is at offset", variable.offset, offset))
+            } else if variable.top_level_array_stride != top_level_array_stride {
+                Some((
+                    "is in an array with a stride of",
+                    variable.top_level_array_stride,
+                    top_level_array_stride,
+                ))
+            } else if variable.array_stride != array_stride {
+                Some((
+                    "has an array stride of",
+                    variable.array_stride,
+                    array_stride,
+                ))
+            } else {
+                None
+            };
+            if let Some((what, shader, rust)) = mismatch {
+                return Err(format!(
+                    "{}: `{}` {} {} in the shader but {} in {}",
+                    self.name,
+                    variable.name,
+                    what,
+                    shader,
+                    rust,
+                    std::any::type_name::<T>()
+                ));
+            }
+        }
+
+        if T::SIZE < self.size {
+            return Err(format!(
+                "{}: the block is {} bytes but {} is only {}",
+                self.name,
+                self.size,
+                std::any::type_name::<T>(),
+                T::SIZE
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Finds the buffer variable `name`, like `hits[0].position`, in `layout`.
+/// Returns its byte offset, the stride of the top-level member if that is an
+/// array of structs and its own array stride, 0 meaning none like for
+/// `BufferVariable`.
+fn locate(mut layout: &Layout, name: &str) -> Option<(usize, usize, usize)> {
+    let mut offset = 0;
+    let mut depth = 0;
+    let mut top_level_array_stride = 0;
+    let mut array_stride = 0;
+    let mut rest = name;
+    while !rest.is_empty() {
+        if let Some(index) = rest.strip_prefix('[') {
+            let (index, after) = index.split_once(']')?;
+            let Layout::Array { stride, element } = layout else {
+                return None;
+            };
+            offset += index.parse::<usize>().ok()? * stride;
+            array_stride = *stride;
+            layout = element;
+            rest = after;
+        } else {
+            let member = rest.strip_prefix('.').unwrap_or(rest);
+            let end = member.find(['[', '.']).unwrap_or(member.len());
+            let Layout::Struct(members) = layout else {
+                return None;
+            };
+            let (_, member_offset, member_layout) =
+                members.iter().find(|(name, ..)| *name == &member[..end])?;
+            // The top-level member only has a stride for the members of its
+            // elements, an array of scalars is the variable itself
+            if depth == 1 {
+                top_level_array_stride = array_stride;
+            }
+            depth += 1;
+            offset += member_offset;
+            array_stride = 0;
+            layout = member_layout;
+            rest = &member[end..];
+        }
+    }
+    // A flat GLSL array may be an array of arrays in Rust, which std430 lays
+    // out the same
+    if array_stride != 0 {
+        while let Layout::Array { stride, element } = layout {
+            array_stride = *stride;
+            layout = element;
+        }
+    }
+    (depth > 0).then_some((offset, top_level_array_stride, array_stride))
+}
+
+impl Program {
+    /// All the active `buffer` blocks, in the order the driver enumerates them.
+    pub fn buffer_blocks(&self) -> Vec<BufferBlock> {
+        let count = self.interface_count(gl::SHADER_STORAGE_BLOCK);
+        (0..count)
+            .map(|index| self.buffer_block_at(index))
+            .collect()
+    }
+
+    /// The active `buffer` block called `name`, the name before the braces.
+    pub fn buffer_block(&self, name: &str) -> Option<BufferBlock> {
+        self.buffer_blocks()
+            .into_iter()
+            .find(|block| block.name == name)
+    }
+
+    /// All the active uniforms that are not in a uniform block.
+    pub fn uniforms(&self) -> Vec<Uniform> {
+        let count = self.interface_count(gl::UNIFORM);
+        (0..count)
+            .filter_map(|index| {
+                let [block_index, location, type_, array_size] = self.resource_properties(
+                    gl::UNIFORM,
+                    index,
+                    [gl::BLOCK_INDEX, gl::LOCATION, gl::TYPE, gl::ARRAY_SIZE],
+                );
+                if block_index != -1 {
+                    return None;
+                }
+                Some(Uniform {
+                    name: self.resource_name(gl::UNIFORM, index),
+                    location,
+                    type_: type_ as GLenum,
+                    array_size: array_size as usize,
+                })
+            })
+            .collect()
+    }
+
+    /// The `local_size_x`, `local_size_y` and `local_size_z` of the compute
+    /// shader.
+    pub fn work_group_size(&self) -> [GLuint; 3] {
+        let mut size = [0; 3];
+        unsafe {
+            gl::GetProgramiv(
+                self.get_id(),
+                gl::COMPUTE_WORK_GROUP_SIZE,
+                size.as_mut_ptr(),
+            )
+        };
+        [size[0] as GLuint, size[1] as GLuint, size[2] as GLuint]
+    }
+
+    fn buffer_block_at(&self, index: GLuint) -> BufferBlock {
+        let name = self.resource_name(gl::SHADER_STORAGE_BLOCK, index);
+        let [binding, size, num_variables] = self.resource_properties(
+            gl::SHADER_STORAGE_BLOCK,
+            index,
+            [
+                gl::BUFFER_BINDING,
+                gl::BUFFER_DATA_SIZE,
+                gl::NUM_ACTIVE_VARIABLES,
+            ],
+        );
+
+        let mut variable_indices = vec![0; num_variables as usize];
+        unsafe {
+            gl::GetProgramResourceiv(
+                self.get_id(),
+                gl::SHADER_STORAGE_BLOCK,
+                index,
+                1,
+                &gl::ACTIVE_VARIABLES,
+                variable_indices.len() as GLsizei,
+                std::ptr::null_mut(),
+                variable_indices.as_mut_ptr(),
+            )
+        };
+
+        // Variables of blocks with an instance name are prefixed by the block
+        // name, e.g. `InputData.data[0]`
+        let prefix = format!("{}.", name);
+        let variables = variable_indices
+            .into_iter()
+            .map(|variable_index| {
+                let variable_index = variable_index as GLuint;
+                let [offset, array_size, array_stride, top_level_array_size, top_level_array_stride] =
+                    self.resource_properties(
+                        gl::BUFFER_VARIABLE,
+                        variable_index,
+                        [
+                            gl::OFFSET,
+                            gl::ARRAY_SIZE,
+                            gl::ARRAY_STRIDE,
+                            gl::TOP_LEVEL_ARRAY_SIZE,
+                            gl::TOP_LEVEL_ARRAY_STRIDE,
+                        ],
+                    );
+                let name = self.resource_name(gl::BUFFER_VARIABLE, variable_index);
+                BufferVariable {
+                    name: name.strip_prefix(&prefix).unwrap_or(&name).to_string(),
+                    offset: offset as usize,
+                    array_size: array_size as usize,
+                    array_stride: array_stride as usize,
+                    top_level_array_size: top_level_array_size as usize,
+                    top_level_array_stride: top_level_array_stride as usize,
+                }
+            })
+            .collect();
+
+        BufferBlock {
+            name,
+            binding: binding as GLuint,
+            size: size as usize,
+            variables,
+        }
+    }
+
+    fn interface_count(&self, interface: GLenum) -> GLuint {
+        let mut count = 0;
+        unsafe {
+            gl::GetProgramInterfaceiv(self.get_id(), interface, gl::ACTIVE_RESOURCES, &mut count)
+        };
+        count as GLuint
+    }
+
+    fn resource_name(&self, interface: GLenum, index: GLuint) -> String {
+        let [len] = self.resource_properties(interface, index, [gl::NAME_LENGTH]);
+        // The length includes the nul terminator
+        let mut name = vec![0u8; len as usize];
+        let mut written = 0;
+        unsafe {
+            gl::GetProgramResourceName(
+                self.get_id(),
+                interface,
+                index,
+                len,
+                &mut written,
+                name.as_mut_ptr() as *mut GLchar,
+            )
+        };
+        name.truncate(written as usize);
+        String::from_utf8_lossy(&name).into_owned()
+    }
+
+    fn resource_properties<const N: usize>(
+        &self,
+        interface: GLenum,
+        index: GLuint,
+        properties: [GLenum; N],
+    ) -> [GLint; N] {
+        let mut values = [0; N];
+        unsafe {
+            gl::GetProgramResourceiv(
+                self.get_id(),
+                interface,
+                index,
+                N as GLsizei,
+                properties.as_ptr(),
+                N as GLsizei,
+                std::ptr::null_mut(),
+                values.as_mut_ptr(),
+            )
+        };
+        values
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::CString;
+
+    use crate::std430::{Vec3, Vec4};
+    use crate::{Context, Program, Shader, Std430};
+
+    const SOURCE: &str = "
+#version 450 core
+layout(local_size_x = 64, local_size_y = 2, local_size_z = 1) in;
+
+struct Hit {
+    vec3 position;
+    float distance;
+};
+
+layout(std430, binding = 3) buffer OutputData {
+    uint length;
+    Hit hits[4];
+    float data[];
+} output_data;
+
+uniform vec3 origin;
+uniform float scale;
+
+void main() {
+    output_data.hits[0].position = origin;
+    output_data.data[gl_GlobalInvocationID.x] = scale * float(output_data.length);
+}
+";
+
+    fn make_program() -> Program {
+        let kernel =
+            Shader::from_source(&CString::new(SOURCE).unwrap(), gl::COMPUTE_SHADER).unwrap();
+        Program::new(vec![(kernel, gl::COMPUTE_SHADER)]).unwrap()
+    }
+
+    #[derive(Copy, Clone, Std430)]
+    struct Hit {
+        position: Vec3,
+        distance: f32,
+    }
+
+    #[derive(Copy, Clone, Std430)]
+    struct OutputData {
+        length: u32,
+        hits: [Hit; 4],
+        data: [f32; 1],
+    }
+
+    #[derive(Copy, Clone, Std430)]
+    struct WrongOutputData {
+        length: u32,
+        // A plain float array is only 4 byte aligned, unlike an array of structs
+        // with a vec3
+        hits: [[f32; 4]; 4],
+        data: [f32; 1],
+    }
+
+    // The members of the array elements are where the shader has them, but the
+    // elements are twice as far apart
+    #[derive(Copy, Clone, Std430)]
+    struct PaddedHit {
+        position: Vec3,
+        distance: f32,
+        padding: Vec4,
+    }
+
+    #[derive(Copy, Clone, Std430)]
+    struct PaddedOutputData {
+        length: u32,
+        hits: [PaddedHit; 2],
+        data: [f32; 1],
+    }
+
+    #[derive(Copy, Clone, Std430)]
+    struct SwappedHit {
+        distance: f32,
+        position: Vec3,
+    }
+
+    #[derive(Copy, Clone, Std430)]
+    struct SwappedOutputData {
+        length: u32,
+        hits: [SwappedHit; 2],
+        data: [f32; 1],
+    }
+
+    #[test]
+    fn test_reflection() {
+        let _context = Context::new().unwrap();
+        let program = make_program();
+
+        assert_eq!(program.work_group_size(), [64, 2, 1]);
+
+        let mut uniforms: Vec<_> = program
+            .uniforms()
+            .into_iter()
+            .map(|uniform| (uniform.name, uniform.type_))
+            .collect();
+        uniforms.sort();
+        assert_eq!(
+            uniforms,
+            vec![
+                ("origin".to_string(), gl::FLOAT_VEC3),
+                ("scale".to_string(), gl::FLOAT)
+            ]
+        );
+
+        let blocks = program.buffer_blocks();
+        assert_eq!(blocks.len(), 1);
+        let block = &blocks[0];
+        assert_eq!(block.name, "OutputData");
+        assert_eq!(block.binding, 3);
+        assert_eq!(
+            block.members(),
+            vec![("length", 0), ("hits", 16), ("data", 80)]
+        );
+        // Drivers may or may not pad the block to its alignment
+        assert!(block.size >= 84, "block size {}", block.size);
+    }
+
+    #[test]
+    fn test_reflection_check_layout() {
+        let _context = Context::new().unwrap();
+        let program = make_program();
+        let block = program.buffer_block("OutputData").unwrap();
+
+        assert_eq!(block.check_layout::<OutputData>(), Ok(()));
+        assert_eq!(
+            block.check_layout::<WrongOutputData>(),
+            Err(format!(
+                "OutputData: member `hits` is at offset 16 in the shader but at 4 in {}",
+                std::any::type_name::<WrongOutputData>()
+            ))
+        );
+        assert!(block.check_layout::<Hit>().is_err());
+
+        // The top-level offsets match, but not inside the array of structs
+        assert_eq!(
+            block.check_layout::<PaddedOutputData>(),
+            Err(format!(
+                "OutputData: `hits[0].position` is in an array with a stride of 16 in the \
+                 shader but 32 in {}",
+                std::any::type_name::<PaddedOutputData>()
+            ))
+        );
+        assert_eq!(
+            block.check_layout::<SwappedOutputData>(),
+            Err(format!(
+                "OutputData: `hits[0].position` is at offset 16 in the shader but 32 in {}",
+                std::any::type_name::<SwappedOutputData>()
+            ))
+        );
+    }
+}
@@ -0,0 +1,278 @@
+use gl::types::*;
+use std::collections::HashMap;
+
+use crate::compact::{Compact, Compacted};
+use crate::element::Element;
+use crate::error::ShaderError;
+use crate::scan::{kernel, ScanKind, ScanOp};
+use crate::segmented_scan::SegmentedScan;
+use crate::template::Param;
+use crate::{Buffer, Program};
+
+// Flags the first element of every run of equal keys. The predicate is pasted
+// into compaction_flags, where the keys are `input_data`
+const HEADS: &str = "i == 0 || x != input_data.data[i - 1]";
+
+/// Finds the runs of equal adjacent keys, e.g. duplicates in sorted keys. The
+/// heads of the runs are flagged, scanned and scattered with a `Compact`.
+pub struct Runs<T: Element> {
+    heads: Compact<T>,
+    ends: Program,
+    counts: Program,
+}
+
+impl<T: Element> Runs<T> {
+    pub fn new() -> Result<Self, ShaderError> {
+        let mut substs: HashMap<&str, Param> = HashMap::new();
+        substs.insert("VALUE", "i + 1".into());
+        Ok(Runs {
+            heads: Compact::new(HEADS)?,
+            ends: kernel(
+                "runs/run_ends.comp.glsl",
+                include_str!("../shaders/runs/run_ends.comp.glsl"),
+                &substs,
+            )?,
+            counts: kernel(
+                "runs/run_counts.comp.glsl",
+                include_str!("../shaders/runs/run_counts.comp.glsl"),
+                &HashMap::new(),
+            )?,
+        })
+    }
+
+    /// Keeps the first key of every run, dropping adjacent duplicates.
+    pub fn unique(&self, keys: &Buffer<T>) -> Compacted<T> {
+        self.heads.run(keys)
+    }
+
+    /// Returns the first key of every run, as `unique` does, and the length of
+    /// every run.
+    pub fn run_length_encode(&self, keys: &Buffer<T>) -> (Compacted<T>, Buffer<GLuint>) {
+        let flags = Buffer::<GLuint>::new(keys.len());
+        let (unique, offsets) = self.find_heads(keys, &flags);
+        let counts = Buffer::<GLuint>::new(keys.len());
+        if let Some(offsets) = offsets {
+            let ends = Buffer::<GLuint>::new(keys.len());
+            gather_run_ends(&self.ends, &flags, &offsets, None, &ends);
+
+            // One thread per run, the work group size of run_counts is the
+            // default of `Compact::dispatch_group_size`
+            ends.bind(0);
+            counts.bind(1);
+            unique.counter.bind(2);
+            unique.dispatch(&self.counts);
+        }
+        (unique, counts)
+    }
+
+    /// Flags the heads of the runs of `keys`, returning the unique keys and the
+    /// scanned flags, if there are any keys.
+    fn find_heads(
+        &self,
+        keys: &Buffer<T>,
+        flags: &Buffer<GLuint>,
+    ) -> (Compacted<T>, Option<Buffer<GLuint>>) {
+        self.heads.flag_and_scan(keys, flags, false)
+    }
+}
+
+/// Combines the values of every run of equal adjacent keys with a `ScanOp`,
+/// e.g. to sum the values of each key after sorting them by key. The values are
+/// scanned with a `SegmentedScan` starting at the heads of the runs, and the
+/// total of each run is gathered from its last element.
+pub struct ReduceByKey<K: Element, V: Element> {
+    runs: Runs<K>,
+    scan: SegmentedScan<V>,
+    gather: Program,
+}
+
+impl<K: Element, V: Element> ReduceByKey<K, V> {
+    pub fn new(op: ScanOp) -> Result<Self, ShaderError> {
+        let mut substs: HashMap<&str, Param> = HashMap::new();
+        substs.insert("TYPE", V::GLSL_TYPE.into());
+        Ok(ReduceByKey {
+            runs: Runs::new()?,
+            scan: SegmentedScan::new(op)?,
+            gather: kernel(
+                "runs/run_ends.comp.glsl",
+                include_str!("../shaders/runs/run_ends.comp.glsl"),
+                &substs,
+            )?,
+        })
+    }
+
+    pub fn op(&self) -> &ScanOp {
+        self.scan.op()
+    }
+
+    /// Returns the first key of every run, as `Runs::unique` does, and the
+    /// combination of the values of every run.
+    pub fn run(&self, keys: &Buffer<K>, values: &Buffer<V>) -> (Compacted<K>, Buffer<V>) {
+        assert_eq!(keys.len(), values.len(), "there must be one value per key");
+
+        let flags = Buffer::<GLuint>::new(keys.len());
+        let (unique, offsets) = self.runs.find_heads(keys, &flags);
+        let reduced = Buffer::<V>::new(keys.len());
+        if let Some(offsets) = offsets {
+            let scanned = self.scan.run(values, &flags, ScanKind::Inclusive);
+            gather_run_ends(&self.gather, &flags, &offsets, Some(&scanned), &reduced);
+        }
+        (unique, reduced)
+    }
+}
+
+fn gather_run_ends<V: Element>(
+    program: &Program,
+    flags: &Buffer<GLuint>,
+    offsets: &Buffer<GLuint>,
+    values: Option<&Buffer<V>>,
+    output: &Buffer<V>,
+) {
+    unsafe { gl::ProgramUniform1ui(program.get_id(), 0, flags.len() as GLuint) };
+    flags.bind(1);
+    offsets.bind(2);
+    if let Some(values) = values {
+        values.bind(3);
+    }
+    output.bind(4);
+    let threads = program.work_group_size()[0] as usize;
+    program.dispatch(flags.len().div_ceil(threads) as GLuint, 1, 1);
+}
+
+/// Drops the adjacent duplicates of `keys` with a one-off `Runs`.
+pub fn unique<T: Element>(keys: &Buffer<T>) -> Result<Compacted<T>, ShaderError> {
+    Ok(Runs::new()?.unique(keys))
+}
+
+/// Run-length encodes `keys` with a one-off `Runs`.
+pub fn run_length_encode<T: Element>(
+    keys: &Buffer<T>,
+) -> Result<(Compacted<T>, Buffer<GLuint>), ShaderError> {
+    Ok(Runs::new()?.run_length_encode(keys))
+}
+
+/// Reduces the values of every run of `keys` with a one-off `ReduceByKey`.
+pub fn reduce_by_key<K: Element, V: Element>(
+    keys: &Buffer<K>,
+    values: &Buffer<V>,
+    op: ScanOp,
+) -> Result<(Compacted<K>, Buffer<V>), ShaderError> {
+    Ok(ReduceByKey::new(op)?.run(keys, values))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{reduce_by_key, run_length_encode, ReduceByKey, Runs};
+    use crate::std430::UVec4;
+    use crate::{Buffer, Context, ScanOp};
+    use rand::Rng;
+
+    // The runs of `keys` as (key, length)
+    fn runs(keys: &[u32]) -> Vec<(u32, u32)> {
+        let mut runs: Vec<(u32, u32)> = Vec::new();
+        for &key in keys {
+            match runs.last_mut() {
+                Some((last, len)) if *last == key => *len += 1,
+                _ => runs.push((key, 1)),
+            }
+        }
+        runs
+    }
+
+    #[test]
+    fn test_runs() {
+        let _context = Context::new().unwrap();
+        let mut rng = rand::thread_rng();
+        let runs_of = Runs::<u32>::new().unwrap();
+
+        // Sorted keys with long runs, short runs, and no duplicates
+        for &(len, max) in &[
+            (0, 1),
+            (1, 1),
+            (300, 1),
+            (1000, 10),
+            (100_000, 1000),
+            (5000, u32::MAX),
+        ] {
+            let mut keys: Vec<u32> = (0..len).map(|_| rng.gen_range(0, max)).collect();
+            keys.sort();
+            let expected = runs(&keys);
+            let keys_ssbo = Buffer::from_slice(&keys);
+
+            let mut deduped = keys.clone();
+            deduped.dedup();
+            assert_eq!(runs_of.unique(&keys_ssbo).to_vec(), deduped, "len {}", len);
+
+            let (values, counts) = runs_of.run_length_encode(&keys_ssbo);
+            assert_eq!(values.len(), expected.len());
+            let encoded: Vec<(u32, u32)> = values
+                .to_vec()
+                .into_iter()
+                .zip(counts.read(0..expected.len()))
+                .collect();
+            assert_eq!(encoded, expected, "len {}", len);
+        }
+    }
+
+    #[test]
+    fn test_reduce_by_key() {
+        let _context = Context::new().unwrap();
+        let mut rng = rand::thread_rng();
+        let sum_by_key = ReduceByKey::<u32, f32>::new(ScanOp::Add).unwrap();
+
+        let mut keys: Vec<u32> = (0..20_000).map(|_| rng.gen_range(0, 300)).collect();
+        keys.sort();
+        let values: Vec<f32> = (0..20_000).map(|_| rng.gen_range(0, 10) as f32).collect();
+        let (unique_keys, sums) =
+            sum_by_key.run(&Buffer::from_slice(&keys), &Buffer::from_slice(&values));
+
+        let mut expected: Vec<(u32, f32)> = Vec::new();
+        for (&key, &value) in keys.iter().zip(&values) {
+            match expected.last_mut() {
+                Some((last, sum)) if *last == key => *sum += value,
+                _ => expected.push((key, value)),
+            }
+        }
+        let runs = unique_keys.len();
+        let reduced: Vec<(u32, f32)> = unique_keys
+            .to_vec()
+            .into_iter()
+            .zip(sums.read(0..runs))
+            .collect();
+        assert_eq!(reduced, expected);
+
+        // Largest value of each run, and empty keys
+        let (_, max) = reduce_by_key(
+            &Buffer::from_slice(&[1u32, 1, 2, 2, 2, 7]),
+            &Buffer::from_slice(&[4i32, -3, -1, 8, 2, 0]),
+            ScanOp::Max,
+        )
+        .unwrap();
+        assert_eq!(max.read(0..3), vec![4, 8, 0]);
+        let (none, _) =
+            reduce_by_key(&Buffer::<u32>::new(0), &Buffer::<u32>::new(0), ScanOp::Add).unwrap();
+        assert!(none.is_empty());
+    }
+
+    #[test]
+    fn test_runs_vector_keys() {
+        let _context = Context::new().unwrap();
+
+        let hits = [
+            UVec4([1, 6, 0, 1337]),
+            UVec4([3, 6, 0, 1337]),
+            UVec4([3, 6, 0, 1337]),
+            UVec4([5, 6, 0, 1337]),
+        ];
+        let (voxels, counts) = run_length_encode(&Buffer::from_slice(&hits)).unwrap();
+        assert_eq!(
+            voxels.to_vec(),
+            vec![
+                UVec4([1, 6, 0, 1337]),
+                UVec4([3, 6, 0, 1337]),
+                UVec4([5, 6, 0, 1337]),
+            ]
+        );
+        assert_eq!(counts.read(0..3), vec![1, 2, 1]);
+    }
+}
@@ -0,0 +1,615 @@
+use gl::types::*;
+use std::collections::HashMap;
+use std::marker::PhantomData;
+
+use crate::cache::gl_string;
+use crate::element::{check_supported, Element};
+use crate::error::ShaderError;
+use crate::template::{Param, ShaderTemplate};
+use crate::{Buffer, Program};
+
+/// Whether the `i`-th element of a scan sums the inputs before `i`, or up to and
+/// including `i`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ScanKind {
+    /// `[a, b, c]` scans to `[a, a + b, a + b + c]`.
+    Inclusive,
+    /// `[a, b, c]` scans to `[0, a, a + b]`.
+    Exclusive,
+}
+
+/// How each block is scanned in shared memory.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ScanAlgorithm {
+    /// The Blelloch scan in `multi_wg_prefix_sum1`.
+    Blelloch,
+    /// The Blelloch scan with padded shared memory to avoid bank conflicts, see
+    /// 39.2.3 Avoiding Bank Conflicts, and coalesced loads and stores.
+    ConflictFree,
+    /// A single dispatch per scan, with each work group looking back at the
+    /// totals of the ones before it, see `decoupled_look_back`. It needs work
+    /// groups that already started to keep making progress while another one
+    /// spins, so drivers known not to guarantee that get `Blelloch` instead.
+    DecoupledLookBack,
+}
+
+/// The associative operator a scan combines the elements with.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ScanOp {
+    Add,
+    Mul,
+    Min,
+    Max,
+    /// Bitwise, for integer elements.
+    And,
+    Or,
+    Xor,
+    /// `op` is a GLSL expression of the elements `a` and `b`, with `a` coming
+    /// first, e.g. `b != 0.0 ? b : a` to carry the last non-zero element
+    /// forward. It must be associative, with `identity` as its identity.
+    /// `TYPE` is the GLSL element type.
+    ///
+    /// `functions` are GLSL function definitions `op` can call, for operators
+    /// that need statements, e.g. loops. It is usually empty.
+    Custom {
+        functions: String,
+        op: String,
+        identity: String,
+    },
+}
+
+impl ScanOp {
+    /// The GLSL functions the operator calls, the expression of the operator
+    /// and the one of its identity for `T`.
+    pub(crate) fn glsl<T: Element>(&self) -> (&str, &str, &str) {
+        match self {
+            ScanOp::Add => ("", "a + b", "TYPE(0)"),
+            ScanOp::Mul => ("", "a * b", "TYPE(1)"),
+            ScanOp::Min => ("", "min(a, b)", T::MAX),
+            ScanOp::Max => ("", "max(a, b)", T::MIN),
+            ScanOp::And => ("", "a & b", "~TYPE(0)"),
+            ScanOp::Or => ("", "a | b", "TYPE(0)"),
+            ScanOp::Xor => ("", "a ^ b", "TYPE(0)"),
+            ScanOp::Custom {
+                functions,
+                op,
+                identity,
+            } => (functions, op, identity),
+        }
+    }
+}
+
+/// A multi work group scan of any `Element` type and length with any `ScanOp`.
+///
+/// By default it uses the Blelloch scan of each block in `multi_wg_prefix_sum1`
+/// and then adds the scanned block totals to every element in
+/// `multi_wg_prefix_sum2`. The block totals are scanned the same way, on the
+/// GPU, as many times as needed. See `ScanAlgorithm` for the alternatives.
+pub struct Scan<T: Element> {
+    op: ScanOp,
+    algorithm: ScanAlgorithm,
+    block_len: usize,
+    block_scan: Program,
+    add_block_sums: Program,
+    _marker: PhantomData<T>,
+}
+
+impl<T: Element> Scan<T> {
+    /// The number of elements scanned by each work group, with one thread per
+    /// two elements.
+    pub const DEFAULT_BLOCK_LEN: usize = 1024;
+
+    pub fn new(op: ScanOp) -> Result<Self, ShaderError> {
+        Self::with_block_len(op, Self::DEFAULT_BLOCK_LEN)
+    }
+
+    /// `block_len` must be a power of two, and at most twice the maximum work
+    /// group size.
+    pub fn with_block_len(op: ScanOp, block_len: usize) -> Result<Self, ShaderError> {
+        Self::with_algorithm(op, ScanAlgorithm::Blelloch, block_len)
+    }
+
+    pub fn with_algorithm(
+        op: ScanOp,
+        algorithm: ScanAlgorithm,
+        block_len: usize,
+    ) -> Result<Self, ShaderError> {
+        let algorithm = match algorithm {
+            ScanAlgorithm::DecoupledLookBack if !has_forward_progress() => ScanAlgorithm::Blelloch,
+            algorithm => algorithm,
+        };
+
+        let (functions, glsl_op, identity) = op.glsl::<T>();
+        let mut substs = kernel_substs::<T>(block_len, functions, glsl_op)?;
+        let add_block_sums = kernel(
+            "multi_wg_prefix_sum/multi_wg_prefix_sum2.comp.glsl",
+            include_str!("../shaders/multi_wg_prefix_sum/multi_wg_prefix_sum2.comp.glsl"),
+            &substs,
+        )?;
+        // Only the block scan pads with the identity
+        substs.insert("IDENTITY", identity.into());
+        let block_scan = match algorithm {
+            ScanAlgorithm::Blelloch => kernel(
+                "multi_wg_prefix_sum/multi_wg_prefix_sum1.comp.glsl",
+                include_str!("../shaders/multi_wg_prefix_sum/multi_wg_prefix_sum1.comp.glsl"),
+                &substs,
+            )?,
+            ScanAlgorithm::ConflictFree => kernel(
+                "multi_wg_prefix_sum/multi_wg_prefix_sum1_conflict_free.comp.glsl",
+                include_str!(
+                    "../shaders/multi_wg_prefix_sum/multi_wg_prefix_sum1_conflict_free.comp.glsl"
+                ),
+                &substs,
+            )?,
+            ScanAlgorithm::DecoupledLookBack => kernel(
+                "multi_wg_prefix_sum/decoupled_look_back.comp.glsl",
+                include_str!("../shaders/multi_wg_prefix_sum/decoupled_look_back.comp.glsl"),
+                &substs,
+            )?,
+        };
+
+        Ok(Scan {
+            op,
+            algorithm,
+            block_len,
+            block_scan,
+            add_block_sums,
+            _marker: PhantomData,
+        })
+    }
+
+    pub fn op(&self) -> &ScanOp {
+        &self.op
+    }
+
+    /// The algorithm used, which differs from the requested one after a
+    /// fallback.
+    pub fn algorithm(&self) -> ScanAlgorithm {
+        self.algorithm
+    }
+
+    pub fn block_len(&self) -> usize {
+        self.block_len
+    }
+
+    /// Returns the inclusive or exclusive scan of `input`, which has the same
+    /// length.
+    ///
+    /// The last block is padded with the identity on the GPU, so the length does
+    /// not need to be a power of two or a multiple of the block length.
+    pub fn run(&self, input: &Buffer<T>, kind: ScanKind) -> Buffer<T> {
+        let output = Buffer::new(input.len());
+        if input.is_empty() {
+            return output;
+        }
+
+        match self.algorithm {
+            ScanAlgorithm::Blelloch | ScanAlgorithm::ConflictFree => {
+                self.scan_into(input, &output, kind)
+            }
+            ScanAlgorithm::DecoupledLookBack => self.scan_look_back(input, &output, kind),
+        }
+        output
+    }
+
+    fn scan_look_back(&self, input: &Buffer<T>, output: &Buffer<T>, kind: ScanKind) {
+        let work_groups = input.len().div_ceil(self.block_len);
+        // Zeroed, so every block starts out INVALID
+        let next_block = Buffer::<GLuint>::new(1);
+        let status = Buffer::<GLuint>::new(work_groups);
+        let aggregates = Buffer::<T>::new(work_groups);
+        let inclusive_prefixes = Buffer::<T>::new(work_groups);
+
+        self.set_len(input.len());
+        self.set_inclusive(kind);
+        input.bind(0);
+        output.bind(1);
+        next_block.bind(3);
+        status.bind(4);
+        aggregates.bind(5);
+        inclusive_prefixes.bind(6);
+        self.block_scan.dispatch(work_groups as GLuint, 1, 1);
+    }
+
+    // Each level scans blocks of `block_len` elements and writes their totals,
+    // which are scanned by the next level until they fit in a single block,
+    // see 39.2.4 Arrays of Arbitrary Size. Nothing is read back to the host.
+    fn scan_into(&self, input: &Buffer<T>, output: &Buffer<T>, kind: ScanKind) {
+        let work_groups = input.len().div_ceil(self.block_len);
+        let block_sums = Buffer::<T>::new(work_groups);
+
+        self.set_len(input.len());
+        self.set_inclusive(kind);
+        input.bind(0);
+        output.bind(1);
+        block_sums.bind(2);
+        self.block_scan.dispatch(work_groups as GLuint, 1, 1);
+
+        // The first block needs nothing added
+        if work_groups > 1 {
+            let scanned_sums = Buffer::<T>::new(work_groups);
+            // Block `W` needs the total of the blocks before it
+            self.scan_into(&block_sums, &scanned_sums, ScanKind::Exclusive);
+
+            // The level below changed the length and the bindings
+            self.set_len(input.len());
+            output.bind(1);
+            scanned_sums.bind(2);
+            self.add_block_sums.dispatch(work_groups as GLuint, 1, 1);
+        }
+    }
+
+    fn set_len(&self, len: usize) {
+        for program in [&self.block_scan, &self.add_block_sums] {
+            unsafe { gl::ProgramUniform1ui(program.get_id(), 0, len as GLuint) };
+        }
+    }
+
+    fn set_inclusive(&self, kind: ScanKind) {
+        let inclusive = (kind == ScanKind::Inclusive) as GLuint;
+        unsafe { gl::ProgramUniform1ui(self.block_scan.get_id(), 1, inclusive) };
+    }
+}
+
+/// Whether work groups that started running keep making progress while others
+/// spin. Neither GL nor GLSL can tell, so this goes by the renderer: the
+/// work groups of mobile GPUs are known to be starved.
+fn has_forward_progress() -> bool {
+    let renderer = gl_string(gl::RENDERER);
+    !["Mali", "Adreno", "PowerVR"]
+        .iter()
+        .any(|name| renderer.contains(name))
+}
+
+/// The parameters the scan kernels share, for elements of type `T`.
+pub(crate) fn kernel_substs<'a, T: Element>(
+    block_len: usize,
+    functions: &str,
+    op: &str,
+) -> Result<HashMap<&'a str, Param>, ShaderError> {
+    assert!(
+        block_len >= 2 && block_len.is_power_of_two(),
+        "the block length must be a power of two, not {}",
+        block_len
+    );
+    check_supported::<T>()?;
+
+    let mut substs: HashMap<&str, Param> = HashMap::new();
+    substs.insert("N", block_len.into());
+    substs.insert("TYPE", T::GLSL_TYPE.into());
+    substs.insert("FUNCTIONS", functions.into());
+    substs.insert("OP", op.into());
+    Ok(substs)
+}
+
+pub(crate) fn kernel(
+    name: &str,
+    source: &str,
+    substs: &HashMap<&str, Param>,
+) -> Result<Program, ShaderError> {
+    let template = ShaderTemplate::parse(name, source)?;
+    let kernel = template.compile(substs, gl::COMPUTE_SHADER)?;
+    Program::new(vec![(kernel, gl::COMPUTE_SHADER)])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Scan, ScanAlgorithm, ScanKind, ScanOp};
+    use crate::std430::{UVec2, Vec4};
+    use crate::{Buffer, Context, Element, ShaderError};
+    use rand::Rng;
+    use std::fmt::Debug;
+
+    type CpuOp = fn(f32, f32) -> f32;
+
+    fn scan_with<T: Copy>(
+        input: &[T],
+        kind: ScanKind,
+        identity: T,
+        op: impl Fn(T, T) -> T,
+    ) -> Vec<T> {
+        let mut total = identity;
+        input
+            .iter()
+            .map(|&value| {
+                let before = total;
+                total = op(total, value);
+                match kind {
+                    ScanKind::Inclusive => total,
+                    ScanKind::Exclusive => before,
+                }
+            })
+            .collect()
+    }
+
+    fn exclusive_scan(input: &[f32]) -> Vec<f32> {
+        scan_with(input, ScanKind::Exclusive, 0.0, |a, b| a + b)
+    }
+
+    fn inclusive_scan(input: &[f32]) -> Vec<f32> {
+        scan_with(input, ScanKind::Inclusive, 0.0, |a, b| a + b)
+    }
+
+    #[test]
+    fn test_scan_any_length() {
+        let _context = Context::new().unwrap();
+        let scan = Scan::<f32>::with_block_len(ScanOp::Add, 16).unwrap();
+        let mut rng = rand::thread_rng();
+
+        for &len in &[0, 1, 2, 3, 7, 15, 16, 17, 31, 97, 256, 257, 1021] {
+            // Small integers, so that the float sums are exact
+            let input: Vec<f32> = (0..len).map(|_| rng.gen_range(0, 4) as f32).collect();
+            let input_ssbo = Buffer::from_slice(&input);
+
+            let exclusive = scan.run(&input_ssbo, ScanKind::Exclusive);
+            assert_eq!(exclusive.len(), len);
+            assert_eq!(exclusive.to_vec(), exclusive_scan(&input), "len {}", len);
+
+            let inclusive = scan.run(&input_ssbo, ScanKind::Inclusive);
+            assert_eq!(inclusive.len(), len);
+            assert_eq!(inclusive.to_vec(), inclusive_scan(&input), "len {}", len);
+        }
+    }
+
+    #[test]
+    fn test_scan_conflict_free() {
+        let _context = Context::new().unwrap();
+        let mut rng = rand::thread_rng();
+
+        // Blocks smaller than, equal to and larger than the 32 banks
+        for &block_len in &[16, 64, 1024] {
+            let scan =
+                Scan::<f32>::with_algorithm(ScanOp::Add, ScanAlgorithm::ConflictFree, block_len)
+                    .unwrap();
+            for &len in &[1, 17, 1000, 5000] {
+                let input: Vec<f32> = (0..len).map(|_| rng.gen_range(0, 4) as f32).collect();
+                let input_ssbo = Buffer::from_slice(&input);
+
+                let exclusive = scan.run(&input_ssbo, ScanKind::Exclusive);
+                assert_eq!(exclusive.to_vec(), exclusive_scan(&input), "len {}", len);
+                let inclusive = scan.run(&input_ssbo, ScanKind::Inclusive);
+                assert_eq!(inclusive.to_vec(), inclusive_scan(&input), "len {}", len);
+            }
+        }
+    }
+
+    #[test]
+    fn test_scan_decoupled_look_back() {
+        let _context = Context::new().unwrap();
+        let mut rng = rand::thread_rng();
+
+        let scan =
+            Scan::<u32>::with_algorithm(ScanOp::Add, ScanAlgorithm::DecoupledLookBack, 16).unwrap();
+        assert_eq!(scan.algorithm(), ScanAlgorithm::DecoupledLookBack);
+        for &len in &[1, 16, 17, 1000, 100_000] {
+            let input: Vec<u32> = (0..len).map(|_| rng.gen_range(0, 4)).collect();
+            let input_ssbo = Buffer::from_slice(&input);
+
+            for kind in [ScanKind::Inclusive, ScanKind::Exclusive] {
+                assert_eq!(
+                    scan.run(&input_ssbo, kind).to_vec(),
+                    scan_with(&input, kind, 0, |a, b| a + b),
+                    "len {} {:?}",
+                    len,
+                    kind
+                );
+            }
+        }
+
+        // Not commutative, so the look-back must combine the blocks in order
+        let last_non_zero = ScanOp::Custom {
+            functions: String::new(),
+            op: "b != 0.0 ? b : a".to_string(),
+            identity: "0.0".to_string(),
+        };
+        let scan = Scan::<f32>::with_algorithm(last_non_zero, ScanAlgorithm::DecoupledLookBack, 16)
+            .unwrap();
+        // Mostly zeros, so that the non-zero values have to be carried across
+        // many blocks
+        let input: Vec<f32> = (0..10_000)
+            .map(|_| {
+                if rng.gen_bool(0.002) {
+                    rng.gen_range(1, 100) as f32
+                } else {
+                    0.0
+                }
+            })
+            .collect();
+        assert_eq!(
+            scan.run(&Buffer::from_slice(&input), ScanKind::Inclusive)
+                .to_vec(),
+            scan_with(&input, ScanKind::Inclusive, 0.0, |a, b| if b != 0.0 {
+                b
+            } else {
+                a
+            })
+        );
+    }
+
+    #[test]
+    fn test_scan_many_levels() {
+        let _context = Context::new().unwrap();
+        // 10_000 elements in blocks of 4 need 7 levels, so more than
+        // block_len² elements is not a problem
+        let scan = Scan::<f32>::with_block_len(ScanOp::Add, 4).unwrap();
+        let mut rng = rand::thread_rng();
+
+        for &len in &[17, 64, 65, 10_000] {
+            let input: Vec<f32> = (0..len).map(|_| rng.gen_range(0, 4) as f32).collect();
+            let output = scan.run(&Buffer::from_slice(&input), ScanKind::Inclusive);
+
+            assert_eq!(output.to_vec(), inclusive_scan(&input), "len {}", len);
+        }
+    }
+
+    #[test]
+    fn test_scan_ops() {
+        let _context = Context::new().unwrap();
+        let mut rng = rand::thread_rng();
+        let input: Vec<f32> = (0..1000)
+            .map(|_| [-1.0, 1.0, 0.0, 3.0][rng.gen_range(0, 4)])
+            .collect();
+
+        let last_non_zero = ScanOp::Custom {
+            functions: String::new(),
+            op: "b != 0.0 ? b : a".to_string(),
+            identity: "0.0".to_string(),
+        };
+        let ops: [(ScanOp, f32, CpuOp); 4] = [
+            (ScanOp::Min, f32::INFINITY, f32::min),
+            (ScanOp::Max, f32::NEG_INFINITY, f32::max),
+            // Not commutative, so the order of the operands is checked too
+            (last_non_zero, 0.0, |a, b| if b != 0.0 { b } else { a }),
+            (ScanOp::Mul, 1.0, |a, b| a * b),
+        ];
+        for (op, identity, cpu_op) in ops {
+            // The product only uses ±1 to stay finite
+            let input: Vec<f32> = match op {
+                ScanOp::Mul => input.iter().map(|x| x.signum()).collect(),
+                _ => input.clone(),
+            };
+            let input_ssbo = Buffer::from_slice(&input);
+            let scan = Scan::<f32>::with_block_len(op.clone(), 16).unwrap();
+
+            for kind in [ScanKind::Inclusive, ScanKind::Exclusive] {
+                assert_eq!(
+                    scan.run(&input_ssbo, kind).to_vec(),
+                    scan_with(&input, kind, identity, cpu_op),
+                    "{:?} {:?}",
+                    op,
+                    kind
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_scan_custom_op_functions() {
+        let _context = Context::new().unwrap();
+        let mut rng = rand::thread_rng();
+
+        // Needs statements, so the operator calls a function
+        let saturating_add = ScanOp::Custom {
+            functions: "
+                uint saturating_add(uint a, uint b) {
+                    uint sum = a + b;
+                    // Wrapped around
+                    if (sum < a) {
+                        return 0xFFFFFFFFu;
+                    }
+                    return sum;
+                }"
+            .to_string(),
+            op: "saturating_add(a, b)".to_string(),
+            identity: "0u".to_string(),
+        };
+        // Large enough to saturate after a few hundred elements
+        let input: Vec<u32> = (0..1000).map(|_| rng.gen_range(0, 1 << 25)).collect();
+        let input_ssbo = Buffer::from_slice(&input);
+        let scan = Scan::<u32>::with_block_len(saturating_add, 16).unwrap();
+
+        for kind in [ScanKind::Inclusive, ScanKind::Exclusive] {
+            assert_eq!(
+                scan.run(&input_ssbo, kind).to_vec(),
+                scan_with(&input, kind, 0, u32::saturating_add)
+            );
+        }
+    }
+
+    fn check_element<T: Element + PartialEq + Debug>(
+        op: ScanOp,
+        input: &[T],
+        identity: T,
+        cpu_op: impl Fn(T, T) -> T + Copy,
+    ) {
+        let scan = Scan::<T>::with_block_len(op.clone(), 8).unwrap();
+        let input_ssbo = Buffer::from_slice(input);
+        for kind in [ScanKind::Inclusive, ScanKind::Exclusive] {
+            assert_eq!(
+                scan.run(&input_ssbo, kind).to_vec(),
+                scan_with(input, kind, identity, cpu_op),
+                "{} {:?} {:?}",
+                T::GLSL_TYPE,
+                op,
+                kind
+            );
+        }
+    }
+
+    #[test]
+    fn test_scan_element_types() {
+        let _context = Context::new().unwrap();
+        let mut rng = rand::thread_rng();
+
+        let ints: Vec<i32> = (0..100).map(|_| rng.gen_range(-1000, 1000)).collect();
+        check_element(ScanOp::Add, &ints, 0, |a, b| a + b);
+        check_element(ScanOp::Min, &ints, i32::MAX, i32::min);
+        check_element(ScanOp::Max, &ints, i32::MIN, i32::max);
+
+        let uints: Vec<u32> = (0..100).map(|_| rng.gen()).collect();
+        check_element(ScanOp::And, &uints, !0, |a, b| a & b);
+        check_element(ScanOp::Or, &uints, 0, |a, b| a | b);
+        check_element(ScanOp::Xor, &uints, 0, |a, b| a ^ b);
+        check_element(ScanOp::Add, &uints, 0, u32::wrapping_add);
+        check_element(ScanOp::Min, &uints, u32::MAX, u32::min);
+
+        let uvecs: Vec<UVec2> = (0..100)
+            .map(|_| UVec2([rng.gen_range(0, 100), rng.gen_range(0, 100)]))
+            .collect();
+        check_element(ScanOp::Add, &uvecs, UVec2([0, 0]), |a, b| {
+            UVec2([a.0[0] + b.0[0], a.0[1] + b.0[1]])
+        });
+
+        let vecs: Vec<Vec4> = (0..100)
+            .map(|_| Vec4(std::array::from_fn(|_| rng.gen_range(-100, 100) as f32)))
+            .collect();
+        check_element(ScanOp::Min, &vecs, Vec4([f32::INFINITY; 4]), |a, b| {
+            Vec4(std::array::from_fn(|i| a.0[i].min(b.0[i])))
+        });
+
+        let doubles: Vec<f64> = (0..100).map(|_| rng.gen_range(0, 4) as f64).collect();
+        check_element(ScanOp::Add, &doubles, 0.0, |a, b| a + b);
+        check_element(ScanOp::Max, &doubles, f64::NEG_INFINITY, f64::max);
+
+        if i64::is_supported() {
+            // Past the range of 32 bit integers
+            let longs: Vec<i64> = (0..100).map(|_| rng.gen_range(-1 << 40, 1 << 40)).collect();
+            check_element(ScanOp::Add, &longs, 0, |a, b| a + b);
+            check_element(ScanOp::Min, &longs, i64::MAX, i64::min);
+
+            let ulongs: Vec<u64> = (0..100).map(|_| rng.gen()).collect();
+            check_element(ScanOp::Xor, &ulongs, 0, |a, b| a ^ b);
+            check_element(ScanOp::Max, &ulongs, 0, u64::max);
+        }
+    }
+
+    #[test]
+    fn test_scan_custom_op_error() {
+        let _context = Context::new().unwrap();
+        let op = ScanOp::Custom {
+            functions: String::new(),
+            op: "a +* b".to_string(),
+            identity: "0.0".to_string(),
+        };
+
+        match Scan::<f32>::new(op) {
+            Err(ShaderError::Compile { shader, .. }) => {
+                assert_eq!(shader, "multi_wg_prefix_sum/multi_wg_prefix_sum2.comp.glsl")
+            }
+            Err(err) => panic!("unexpected error: {}", err),
+            Ok(_) => panic!("the operator should not compile"),
+        }
+    }
+
+    #[test]
+    fn test_scan_default_block_len() {
+        let _context = Context::new().unwrap();
+        let scan = Scan::<f32>::new(ScanOp::Add).unwrap();
+        let mut rng = rand::thread_rng();
+
+        let input: Vec<f32> = (0..262_147).map(|_| rng.gen_range(0, 4) as f32).collect();
+        let output = scan.run(&Buffer::from_slice(&input), ScanKind::Exclusive);
+
+        assert_eq!(output.to_vec(), exclusive_scan(&input));
+    }
+}
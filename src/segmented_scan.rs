@@ -0,0 +1,209 @@
+use gl::types::*;
+use std::marker::PhantomData;
+
+use crate::element::Element;
+use crate::error::ShaderError;
+use crate::scan::{kernel, kernel_substs, ScanKind, ScanOp};
+use crate::{Buffer, Program};
+
+// The values of `mode` in segmented_prefix_sum1
+const INCLUSIVE: GLuint = 0;
+const EXCLUSIVE: GLuint = 1;
+const CARRY: GLuint = 2;
+
+/// A scan that restarts at every head flag, e.g. to scan each chunk of one
+/// concatenated array on its own. Like `Scan`, it works on any `Element`
+/// type, length and `ScanOp`, and scans the block sums on the GPU.
+pub struct SegmentedScan<T: Element> {
+    op: ScanOp,
+    block_len: usize,
+    block_scan: Program,
+    add_block_sums: Program,
+    _marker: PhantomData<T>,
+}
+
+impl<T: Element> SegmentedScan<T> {
+    pub const DEFAULT_BLOCK_LEN: usize = 1024;
+
+    pub fn new(op: ScanOp) -> Result<Self, ShaderError> {
+        Self::with_block_len(op, Self::DEFAULT_BLOCK_LEN)
+    }
+
+    /// `block_len` must be a power of two, and at most twice the maximum work
+    /// group size.
+    pub fn with_block_len(op: ScanOp, block_len: usize) -> Result<Self, ShaderError> {
+        let (functions, glsl_op, identity) = op.glsl::<T>();
+        let mut substs = kernel_substs::<T>(block_len, functions, glsl_op)?;
+        let add_block_sums = kernel(
+            "segmented_prefix_sum/segmented_prefix_sum2.comp.glsl",
+            include_str!("../shaders/segmented_prefix_sum/segmented_prefix_sum2.comp.glsl"),
+            &substs,
+        )?;
+        substs.insert("IDENTITY", identity.into());
+        let block_scan = kernel(
+            "segmented_prefix_sum/segmented_prefix_sum1.comp.glsl",
+            include_str!("../shaders/segmented_prefix_sum/segmented_prefix_sum1.comp.glsl"),
+            &substs,
+        )?;
+
+        Ok(SegmentedScan {
+            op,
+            block_len,
+            block_scan,
+            add_block_sums,
+            _marker: PhantomData,
+        })
+    }
+
+    pub fn op(&self) -> &ScanOp {
+        &self.op
+    }
+
+    pub fn block_len(&self) -> usize {
+        self.block_len
+    }
+
+    /// Returns the scan of `input` where every element with a non-zero flag in
+    /// `heads` starts a new segment. The first element always does.
+    ///
+    /// To scan segments given by their start offsets, set the flags at the
+    /// offsets.
+    pub fn run(&self, input: &Buffer<T>, heads: &Buffer<GLuint>, kind: ScanKind) -> Buffer<T> {
+        assert_eq!(
+            input.len(),
+            heads.len(),
+            "there must be one head flag per element"
+        );
+
+        let output = Buffer::new(input.len());
+        if !input.is_empty() {
+            let mode = match kind {
+                ScanKind::Inclusive => INCLUSIVE,
+                ScanKind::Exclusive => EXCLUSIVE,
+            };
+            self.scan_into(input, heads, &output, mode);
+        }
+        output
+    }
+
+    // Like Scan::scan_into, with the head flags of the block sums being
+    // whether each block has a head
+    fn scan_into(
+        &self,
+        input: &Buffer<T>,
+        heads: &Buffer<GLuint>,
+        output: &Buffer<T>,
+        mode: GLuint,
+    ) {
+        let work_groups = input.len().div_ceil(self.block_len);
+        let block_sums = Buffer::<T>::new(work_groups);
+        let block_heads = Buffer::<GLuint>::new(work_groups);
+        let needs_carry = Buffer::<GLuint>::new(input.len());
+
+        self.set_len(input.len());
+        unsafe { gl::ProgramUniform1ui(self.block_scan.get_id(), 1, mode) };
+        input.bind(0);
+        output.bind(1);
+        block_sums.bind(2);
+        heads.bind(3);
+        block_heads.bind(4);
+        needs_carry.bind(5);
+        self.block_scan.dispatch(work_groups as GLuint, 1, 1);
+
+        if work_groups > 1 {
+            let carries = Buffer::<T>::new(work_groups);
+            self.scan_into(&block_sums, &block_heads, &carries, CARRY);
+
+            self.set_len(input.len());
+            output.bind(1);
+            carries.bind(2);
+            needs_carry.bind(5);
+            self.add_block_sums.dispatch(work_groups as GLuint, 1, 1);
+        }
+    }
+
+    fn set_len(&self, len: usize) {
+        for program in [&self.block_scan, &self.add_block_sums] {
+            unsafe { gl::ProgramUniform1ui(program.get_id(), 0, len as GLuint) };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SegmentedScan;
+    use crate::{Buffer, Context, ScanKind, ScanOp};
+    use rand::Rng;
+
+    fn segmented_scan(input: &[u32], heads: &[u32], kind: ScanKind) -> Vec<u32> {
+        let mut total = 0;
+        input
+            .iter()
+            .zip(heads)
+            .map(|(&value, &head)| {
+                if head != 0 {
+                    total = 0;
+                }
+                let before = total;
+                total += value;
+                match kind {
+                    ScanKind::Inclusive => total,
+                    ScanKind::Exclusive => before,
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_segmented_scan() {
+        let _context = Context::new().unwrap();
+        let scan = SegmentedScan::<u32>::with_block_len(ScanOp::Add, 8).unwrap();
+        let mut rng = rand::thread_rng();
+
+        // Short segments, segments spanning many blocks and levels, and heads
+        // on block boundaries
+        for &(len, head_probability) in &[
+            (0, 0.5),
+            (1, 0.5),
+            (13, 0.3),
+            (100, 0.2),
+            (1000, 0.01),
+            (1000, 0.0),
+            (1024, 0.125),
+            (1024, 1.0),
+        ] {
+            let input: Vec<u32> = (0..len).map(|_| rng.gen_range(0, 10)).collect();
+            let heads: Vec<u32> = (0..len)
+                .map(|_| rng.gen_bool(head_probability) as u32)
+                .collect();
+            let input_ssbo = Buffer::from_slice(&input);
+            let heads_ssbo = Buffer::from_slice(&heads);
+
+            for kind in [ScanKind::Inclusive, ScanKind::Exclusive] {
+                assert_eq!(
+                    scan.run(&input_ssbo, &heads_ssbo, kind).to_vec(),
+                    segmented_scan(&input, &heads, kind),
+                    "len {} {:?}",
+                    len,
+                    kind
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_segmented_scan_chunk_offsets() {
+        let _context = Context::new().unwrap();
+        let scan = SegmentedScan::<u32>::new(ScanOp::Add).unwrap();
+
+        // The face offsets of each of three chunks, from their face counts
+        let faces = [3, 1, 2, 5, 0, 4, 4];
+        let heads = [1, 0, 0, 1, 1, 0, 0];
+        let offsets = scan.run(
+            &Buffer::from_slice(&faces),
+            &Buffer::from_slice(&heads),
+            ScanKind::Exclusive,
+        );
+        assert_eq!(offsets.to_vec(), vec![0, 3, 4, 0, 0, 0, 4]);
+    }
+}
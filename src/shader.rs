@@ -1,12 +1,25 @@
 use gl::types::*;
 use std::ffi::CStr;
 use std::ffi::CString;
+
+use crate::error::{Diagnostic, ShaderError};
+
 pub struct Shader {
     id: GLuint,
 }
 
 impl Shader {
-    pub fn from_source(source: &CStr, kind: GLenum) -> Result<Shader, String> {
+    pub fn from_source(source: &CStr, kind: GLenum) -> Result<Shader, ShaderError> {
+        Self::from_named_source("shader", source, kind)
+    }
+
+    /// Like `from_source`, but `name` is used in the error, usually the file
+    /// name.
+    pub fn from_named_source(
+        name: &str,
+        source: &CStr,
+        kind: GLenum,
+    ) -> Result<Shader, ShaderError> {
         let id = unsafe { gl::CreateShader(kind) };
         unsafe {
             gl::ShaderSource(id, 1, &source.as_ptr(), std::ptr::null());
@@ -33,7 +46,11 @@ impl Shader {
                 );
             }
 
-            return Err(error.to_string_lossy().to_string());
+            return Err(ShaderError::Compile {
+                shader: name.to_string(),
+                source: source.to_string_lossy().into_owned(),
+                diagnostics: Diagnostic::parse_info_log(&error.to_string_lossy()),
+            });
         }
         Ok(Shader { id })
     }
@@ -0,0 +1,269 @@
+// The std430 layout rules, from the OpenGL 4.5 spec, section 7.6.2.2 "Standard
+// Uniform Block Layout", minus the std140 rounding of arrays and structs to vec4:
+// - scalars are aligned to their size, bools are stored as 4 byte uints
+// - two and four component vectors are aligned to 2 and 4 times their component,
+//   three component vectors like four component ones
+// - arrays are aligned like their elements, with a stride of the element size
+//   rounded up to its alignment
+// - structs are aligned to their most aligned member, and padded at the end to
+//   a multiple of that
+
+/// A type with a known GLSL std430 layout, which can be copied in and out of a
+/// shader storage buffer without any hand-padding on the Rust side.
+///
+/// Implemented for `u32`, `i32`, `u64`, `i64`, `f32`, `f64`, `bool`, the GLSL
+/// vector types in this module and arrays of any of them. Structs can
+/// `#[derive(Std430)]`.
+pub trait Std430: Copy {
+    /// The base alignment in bytes.
+    const ALIGN: usize;
+    /// The size in bytes, without the padding added in arrays.
+    const SIZE: usize;
+    /// The distance in bytes between consecutive elements of an array.
+    const STRIDE: usize = align_to(Self::SIZE, Self::ALIGN);
+    /// The name and byte offset of each member, for structs.
+    const FIELDS: &'static [(&'static str, usize)] = &[];
+    /// The array strides and struct members, recursively, which
+    /// `BufferBlock::check_layout` compares with the driver's.
+    const LAYOUT: Layout = Layout::Value;
+
+    /// Writes `self` into `bytes`, which is exactly `SIZE` long.
+    fn write_std430(&self, bytes: &mut [u8]);
+    /// Reads a value back from `bytes`, which is exactly `SIZE` long.
+    fn read_std430(bytes: &[u8]) -> Self;
+}
+
+/// How a `Std430` type is nested, see `Std430::LAYOUT`.
+#[derive(Debug)]
+pub enum Layout {
+    /// A scalar or a vector.
+    Value,
+    Array {
+        stride: usize,
+        element: &'static Layout,
+    },
+    /// The name, byte offset and layout of each member.
+    Struct(&'static [(&'static str, usize, &'static Layout)]),
+}
+
+/// Rounds `offset` up to the next multiple of `align`, a power of two.
+pub const fn align_to(offset: usize, align: usize) -> usize {
+    (offset + align - 1) & !(align - 1)
+}
+
+pub const fn max(a: usize, b: usize) -> usize {
+    if a > b {
+        a
+    } else {
+        b
+    }
+}
+
+macro_rules! impl_scalar {
+    ($($ty:ty),*) => {
+        $(
+            impl Std430 for $ty {
+                const ALIGN: usize = std::mem::size_of::<$ty>();
+                const SIZE: usize = std::mem::size_of::<$ty>();
+
+                fn write_std430(&self, bytes: &mut [u8]) {
+                    bytes.copy_from_slice(&self.to_ne_bytes());
+                }
+
+                fn read_std430(bytes: &[u8]) -> Self {
+                    let mut array = [0; std::mem::size_of::<$ty>()];
+                    array.copy_from_slice(bytes);
+                    <$ty>::from_ne_bytes(array)
+                }
+            }
+        )*
+    };
+}
+
+impl_scalar!(u32, i32, u64, i64, f32, f64);
+
+impl Std430 for bool {
+    const ALIGN: usize = 4;
+    const SIZE: usize = 4;
+
+    fn write_std430(&self, bytes: &mut [u8]) {
+        (*self as u32).write_std430(bytes)
+    }
+
+    fn read_std430(bytes: &[u8]) -> Self {
+        u32::read_std430(bytes) != 0
+    }
+}
+
+impl<T: Std430, const N: usize> Std430 for [T; N] {
+    const ALIGN: usize = T::ALIGN;
+    const SIZE: usize = N * T::STRIDE;
+    const LAYOUT: Layout = Layout::Array {
+        stride: T::STRIDE,
+        element: &T::LAYOUT,
+    };
+
+    fn write_std430(&self, bytes: &mut [u8]) {
+        for (element, bytes) in self.iter().zip(bytes.chunks_mut(T::STRIDE)) {
+            element.write_std430(&mut bytes[..T::SIZE]);
+        }
+    }
+
+    fn read_std430(bytes: &[u8]) -> Self {
+        std::array::from_fn(|i| T::read_std430(&bytes[i * T::STRIDE..][..T::SIZE]))
+    }
+}
+
+macro_rules! impl_vector {
+    ($($name:ident: [$ty:ty; $n:expr], align $align:expr;)*) => {
+        $(
+            /// The GLSL vector type of the same name, aligned like it in std430.
+            #[derive(Debug, Copy, Clone, PartialEq, Default)]
+            pub struct $name(pub [$ty; $n]);
+
+            impl Std430 for $name {
+                const ALIGN: usize = $align * std::mem::size_of::<$ty>();
+                const SIZE: usize = $n * std::mem::size_of::<$ty>();
+
+                fn write_std430(&self, bytes: &mut [u8]) {
+                    self.0.write_std430(bytes)
+                }
+
+                fn read_std430(bytes: &[u8]) -> Self {
+                    $name(<[$ty; $n]>::read_std430(bytes))
+                }
+            }
+
+            impl From<[$ty; $n]> for $name {
+                fn from(array: [$ty; $n]) -> Self {
+                    $name(array)
+                }
+            }
+        )*
+    };
+}
+
+impl_vector! {
+    Vec2: [f32; 2], align 2;
+    Vec3: [f32; 3], align 4;
+    Vec4: [f32; 4], align 4;
+    IVec2: [i32; 2], align 2;
+    IVec3: [i32; 3], align 4;
+    IVec4: [i32; 4], align 4;
+    UVec2: [u32; 2], align 2;
+    UVec3: [u32; 3], align 4;
+    UVec4: [u32; 4], align 4;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Std430;
+
+    #[derive(Debug, Copy, Clone, PartialEq, Std430)]
+    struct Hit {
+        position: Vec3,
+        distance: f32,
+        has_hit: bool,
+    }
+
+    #[derive(Debug, Copy, Clone, PartialEq, Std430)]
+    struct OutputData {
+        sums: [u32; 3],
+        hits: [UVec4; 2],
+        nearest: Hit,
+        all: [Hit; 2],
+        total: f64,
+    }
+
+    #[test]
+    fn test_std430_scalars_and_vectors() {
+        assert_eq!((u32::ALIGN, u32::SIZE, u32::STRIDE), (4, 4, 4));
+        assert_eq!((bool::ALIGN, bool::SIZE), (4, 4));
+        assert_eq!((f64::ALIGN, f64::SIZE), (8, 8));
+        assert_eq!((Vec2::ALIGN, Vec2::SIZE, Vec2::STRIDE), (8, 8, 8));
+        assert_eq!((Vec3::ALIGN, Vec3::SIZE, Vec3::STRIDE), (16, 12, 16));
+        assert_eq!((UVec4::ALIGN, UVec4::SIZE, UVec4::STRIDE), (16, 16, 16));
+    }
+
+    #[test]
+    fn test_std430_arrays_are_not_rounded_to_vec4() {
+        assert_eq!(<[f32; 3]>::ALIGN, 4);
+        assert_eq!(<[f32; 3]>::SIZE, 12);
+        assert_eq!(<[Vec3; 3]>::SIZE, 48);
+    }
+
+    #[test]
+    fn test_std430_struct_layout() {
+        // A float fits in the padding after a vec3
+        assert_eq!(
+            Hit::FIELDS,
+            &[("position", 0), ("distance", 12), ("has_hit", 16)]
+        );
+        assert_eq!((Hit::ALIGN, Hit::SIZE), (16, 32));
+
+        assert_eq!(
+            OutputData::FIELDS,
+            &[
+                ("sums", 0),
+                ("hits", 16),
+                ("nearest", 48),
+                ("all", 80),
+                ("total", 144),
+            ]
+        );
+        assert_eq!((OutputData::ALIGN, OutputData::SIZE), (16, 160));
+    }
+
+    #[derive(Debug, Copy, Clone, PartialEq, Std430)]
+    struct Pair<T> {
+        key: u32,
+        value: T,
+    }
+
+    #[test]
+    fn test_std430_generic_struct() {
+        assert_eq!(Pair::<f32>::FIELDS, &[("key", 0), ("value", 4)]);
+        assert_eq!((Pair::<f32>::ALIGN, Pair::<f32>::SIZE), (4, 8));
+        assert_eq!(Pair::<Vec3>::FIELDS, &[("key", 0), ("value", 16)]);
+        assert_eq!((Pair::<Vec3>::ALIGN, Pair::<Vec3>::SIZE), (16, 32));
+
+        let pair = Pair {
+            key: 7,
+            value: Vec2([1.0, 2.0]),
+        };
+        let mut bytes = vec![0; Pair::<Vec2>::SIZE];
+        pair.write_std430(&mut bytes);
+        assert_eq!(Pair::read_std430(&bytes), pair);
+    }
+
+    #[test]
+    fn test_std430_round_trip() {
+        let hit = Hit {
+            position: Vec3([1.0, 2.0, 3.0]),
+            distance: 4.0,
+            has_hit: true,
+        };
+        let data = OutputData {
+            sums: [1, 2, 3],
+            hits: [UVec4([4, 5, 6, 7]), UVec4([8, 9, 10, 11])],
+            nearest: hit,
+            all: [
+                hit,
+                Hit {
+                    has_hit: false,
+                    ..hit
+                },
+            ],
+            total: 12.5,
+        };
+
+        let mut bytes = vec![0xff; OutputData::SIZE];
+        data.write_std430(&mut bytes);
+        assert_eq!(OutputData::read_std430(&bytes), data);
+
+        assert_eq!(u32::read_std430(&bytes[16..20]), 4);
+        assert_eq!(f32::read_std430(&bytes[60..64]), 4.0);
+        assert_eq!(u32::read_std430(&bytes[64..68]), 1);
+    }
+}
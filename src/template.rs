@@ -0,0 +1,442 @@
+use gl::types::*;
+use glsl::parser::Parse;
+use glsl::syntax::{PreprocessorDefine, ShaderStage};
+use glsl::visitor::{Host, Visit, Visitor};
+use std::collections::HashMap;
+use std::ffi::CString;
+use std::fmt;
+use std::ops::Range;
+
+use crate::error::ShaderError;
+use crate::shader::Shader;
+
+/// The value of a shader template parameter, spliced into its `#define`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Param {
+    Int(i64),
+    Float(f64),
+    Bool(bool),
+    /// Any GLSL code, e.g. an expression or function definitions, inserted
+    /// verbatim. Several lines are joined into one, without their `//`
+    /// comments, since the value of a `#define` ends with its line. GLSL has
+    /// no string literals, so `//` always starts a comment.
+    Expr(String),
+}
+
+impl Param {
+    fn to_glsl(&self) -> Option<String> {
+        match self {
+            Param::Int(value) => Some(value.to_string()),
+            // Debug always prints a decimal point or an exponent, so the
+            // literal stays a float in GLSL
+            Param::Float(value) if value.is_finite() => Some(format!("{:?}", value)),
+            Param::Float(_) => None,
+            Param::Bool(value) => Some(value.to_string()),
+            Param::Expr(expr) if expr.contains('\n') => Some(
+                expr.lines()
+                    .map(|line| line.split("//").next().unwrap().trim())
+                    .collect::<Vec<_>>()
+                    .join(" "),
+            ),
+            Param::Expr(expr) => Some(expr.clone()),
+        }
+    }
+}
+
+macro_rules! impl_from_int {
+    ($($ty:ty),*) => {
+        $(
+            impl From<$ty> for Param {
+                fn from(value: $ty) -> Self {
+                    Param::Int(value as i64)
+                }
+            }
+        )*
+    };
+}
+
+impl_from_int!(i32, u32, i64, usize);
+
+impl From<f32> for Param {
+    fn from(value: f32) -> Self {
+        Param::Float(value.into())
+    }
+}
+
+impl From<f64> for Param {
+    fn from(value: f64) -> Self {
+        Param::Float(value)
+    }
+}
+
+impl From<bool> for Param {
+    fn from(value: bool) -> Self {
+        Param::Bool(value)
+    }
+}
+
+impl From<&str> for Param {
+    fn from(expr: &str) -> Self {
+        Param::Expr(expr.to_string())
+    }
+}
+
+impl From<String> for Param {
+    fn from(expr: String) -> Self {
+        Param::Expr(expr)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TemplateError {
+    Parse {
+        shader: String,
+        message: String,
+    },
+    /// A parameter declared without a value was not given one.
+    MissingParameter {
+        shader: String,
+        define: String,
+    },
+    /// A value was given for a name the shader does not `#define`.
+    UnknownParameter {
+        shader: String,
+        define: String,
+    },
+    /// The value cannot be written as a GLSL literal, like a NaN.
+    InvalidValue {
+        shader: String,
+        define: String,
+        value: Param,
+    },
+}
+
+impl fmt::Display for TemplateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TemplateError::Parse { shader, message } => {
+                write!(f, "{}: failed to parse GLSL: {}", shader, message)
+            }
+            TemplateError::MissingParameter { shader, define } => {
+                write!(
+                    f,
+                    "{}: missing value for template parameter `{}`",
+                    shader, define
+                )
+            }
+            TemplateError::UnknownParameter { shader, define } => write!(
+                f,
+                "{}: `{}` is not a template parameter, it is never #define'd",
+                shader, define
+            ),
+            TemplateError::InvalidValue {
+                shader,
+                define,
+                value,
+            } => write!(
+                f,
+                "{}: {:?} is not a valid value for template parameter `{}`",
+                shader, value, define
+            ),
+        }
+    }
+}
+
+impl std::error::Error for TemplateError {}
+
+/// A GLSL shader whose object-like `#define`s are its parameters.
+///
+/// A define without a value (`#define N`) is a required parameter, one with a
+/// value (`#define MAX_ITERS 100`) is an optional one with a default.
+pub struct ShaderTemplate {
+    name: String,
+    source: String,
+    params: Vec<(String, Option<String>)>,
+}
+
+impl ShaderTemplate {
+    /// Parses `source`; `name` is only used in error messages, usually the file
+    /// name.
+    pub fn parse(name: &str, source: &str) -> Result<Self, TemplateError> {
+        let shader = ShaderStage::parse(source).map_err(|err| TemplateError::Parse {
+            shader: name.to_string(),
+            message: err.info,
+        })?;
+
+        struct ParamsVisitor {
+            params: Vec<(String, Option<String>)>,
+        }
+        impl Visitor for ParamsVisitor {
+            fn visit_preprocessor_define(&mut self, define: &mut PreprocessorDefine) -> Visit {
+                if let PreprocessorDefine::ObjectLike { ident, value } = define {
+                    let value = value.trim();
+                    let default = if value.is_empty() {
+                        None
+                    } else {
+                        Some(value.to_string())
+                    };
+                    self.params.push((ident.as_str().to_string(), default));
+                }
+
+                Visit::Parent
+            }
+        }
+
+        let mut params_visitor = ParamsVisitor { params: Vec::new() };
+        // Visiting needs a mutable shader even though nothing is changed
+        let mut shader = shader;
+        shader.visit(&mut params_visitor);
+
+        Ok(ShaderTemplate {
+            name: name.to_string(),
+            source: source.to_string(),
+            params: params_visitor.params,
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The parameters the shader declares, in order, with their default
+    /// values. Required parameters have none.
+    pub fn params(&self) -> impl Iterator<Item = (&str, Option<&str>)> {
+        self.params
+            .iter()
+            .map(|(name, default)| (name.as_str(), default.as_deref()))
+    }
+
+    /// Returns the GLSL source with every parameter in `substs` replaced by its
+    /// value. Everything else, including the line numbers, is left as is.
+    pub fn instantiate(&self, substs: &HashMap<&str, Param>) -> Result<String, TemplateError> {
+        let mut keys: Vec<&&str> = substs.keys().collect();
+        keys.sort();
+        for key in keys {
+            if !self.params.iter().any(|(name, _)| name == key) {
+                return Err(TemplateError::UnknownParameter {
+                    shader: self.name.clone(),
+                    define: key.to_string(),
+                });
+            }
+        }
+
+        let mut values = HashMap::new();
+        for (name, default) in &self.params {
+            match (substs.get(name.as_str()), default) {
+                (Some(param), _) => {
+                    let value = param.to_glsl().ok_or_else(|| TemplateError::InvalidValue {
+                        shader: self.name.clone(),
+                        define: name.clone(),
+                        value: param.clone(),
+                    })?;
+                    values.insert(name.as_str(), value);
+                }
+                (None, Some(_)) => (),
+                (None, None) => {
+                    return Err(TemplateError::MissingParameter {
+                        shader: self.name.clone(),
+                        define: name.clone(),
+                    })
+                }
+            }
+        }
+
+        // The values are spliced into the original text instead of printing the
+        // parsed shader back, so that the line numbers in the driver's
+        // diagnostics still match the file
+        let mut transformed_source = String::with_capacity(self.source.len());
+        for line in self.source.split_inclusive('\n') {
+            match define_value_range(line) {
+                Some((name, range)) if values.contains_key(name) => {
+                    transformed_source += &line[..range.start];
+                    transformed_source += " ";
+                    transformed_source += &values[name];
+                    transformed_source += &line[range.end..];
+                }
+                _ => transformed_source += line,
+            }
+        }
+
+        Ok(transformed_source)
+    }
+
+    /// Instantiates the template and compiles it as a shader of type `kind`.
+    pub fn compile(
+        &self,
+        substs: &HashMap<&str, Param>,
+        kind: GLenum,
+    ) -> Result<Shader, ShaderError> {
+        let source = self.instantiate(substs)?;
+        // Only the defines differ, so render the diagnostics with the template
+        // the user wrote
+        Shader::from_named_source(&self.name, &CString::new(source).unwrap(), kind).map_err(|err| {
+            match err {
+                ShaderError::Compile {
+                    shader,
+                    diagnostics,
+                    ..
+                } => ShaderError::Compile {
+                    shader,
+                    source: self.source.clone(),
+                    diagnostics,
+                },
+                err => err,
+            }
+        })
+    }
+}
+
+/// If `line` is an object-like `#define`, returns its name and the byte range
+/// of its value, including the whitespace before it but not the line ending.
+fn define_value_range(line: &str) -> Option<(&str, Range<usize>)> {
+    let directive = line.trim_start().strip_prefix('#')?.trim_start();
+    let rest = directive.strip_prefix("define")?;
+    if !rest.starts_with([' ', '\t']) {
+        return None;
+    }
+    let rest = rest.trim_start();
+    let name_len = rest
+        .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+        .unwrap_or(rest.len());
+    let (name, value) = rest.split_at(name_len);
+    // Function-like macros have the parenthesis right after the name
+    if name.is_empty() || value.starts_with('(') {
+        return None;
+    }
+
+    let start = line.len() - value.len();
+    let end = line.trim_end_matches(['\n', '\r']).len();
+    Some((name, start..end))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Param, ShaderTemplate, TemplateError};
+    use crate::error::{Severity, ShaderError};
+    use crate::Context;
+    use std::collections::HashMap;
+
+    const SOURCE: &str = "
+#version 450 core
+#define N
+#define SCALE
+#define CLAMP
+#define OP(a, b) ((a) + (b))
+#define MAX_ITERS 100
+layout(local_size_x = N, local_size_y = 1, local_size_z = 1) in;
+void main() {}
+";
+
+    #[test]
+    fn test_template_params() {
+        let template = ShaderTemplate::parse("test.comp.glsl", SOURCE).unwrap();
+
+        assert_eq!(
+            template.params().collect::<Vec<_>>(),
+            vec![
+                ("N", None),
+                ("SCALE", None),
+                ("CLAMP", None),
+                ("MAX_ITERS", Some("100"))
+            ]
+        );
+    }
+
+    #[test]
+    fn test_template_instantiate() {
+        let template = ShaderTemplate::parse("test.comp.glsl", SOURCE).unwrap();
+
+        let mut substs: HashMap<&str, Param> = HashMap::new();
+        substs.insert("N", 64usize.into());
+        substs.insert("SCALE", 2.0f32.into());
+        substs.insert("CLAMP", false.into());
+        let source = template.instantiate(&substs).unwrap();
+        assert_eq!(
+            source,
+            SOURCE
+                .replace("#define N\n", "#define N 64\n")
+                .replace("#define SCALE\n", "#define SCALE 2.0\n")
+                .replace("#define CLAMP\n", "#define CLAMP false\n")
+        );
+
+        substs.insert("MAX_ITERS", "N * 2".into());
+        let source = template.instantiate(&substs).unwrap();
+        assert!(source.contains("#define MAX_ITERS N * 2\n"));
+
+        // Several lines are joined into one, so that the line numbers are kept
+        substs.insert(
+            "MAX_ITERS",
+            "max_iters(N)\n  // One per element\n  * 2".into(),
+        );
+        let source = template.instantiate(&substs).unwrap();
+        assert!(source.contains("#define MAX_ITERS max_iters(N)  * 2\n"));
+        assert_eq!(source.lines().count(), SOURCE.lines().count());
+    }
+
+    #[test]
+    fn test_template_errors() {
+        let template = ShaderTemplate::parse("test.comp.glsl", SOURCE).unwrap();
+
+        let mut substs: HashMap<&str, Param> = HashMap::new();
+        substs.insert("N", 64usize.into());
+        substs.insert("SCALE", 2.0f32.into());
+        assert_eq!(
+            template.instantiate(&substs),
+            Err(TemplateError::MissingParameter {
+                shader: "test.comp.glsl".to_string(),
+                define: "CLAMP".to_string()
+            })
+        );
+
+        substs.insert("CLAMP", true.into());
+        substs.insert("M", 1usize.into());
+        let err = template.instantiate(&substs).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "test.comp.glsl: `M` is not a template parameter, it is never #define'd"
+        );
+
+        substs.remove("M");
+        substs.insert("SCALE", f32::NAN.into());
+        match template.instantiate(&substs) {
+            Err(TemplateError::InvalidValue { define, .. }) => assert_eq!(define, "SCALE"),
+            other => panic!("expected an invalid value error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_template_compile_error_lines() {
+        let _context = Context::new().unwrap();
+        let template = ShaderTemplate::parse("test.comp.glsl", SOURCE).unwrap();
+
+        let mut substs: HashMap<&str, Param> = HashMap::new();
+        substs.insert("N", 64usize.into());
+        assert_eq!(
+            template.compile(&substs, gl::COMPUTE_SHADER).err(),
+            Some(ShaderError::Template(TemplateError::MissingParameter {
+                shader: "test.comp.glsl".to_string(),
+                define: "SCALE".to_string()
+            }))
+        );
+
+        let broken = SOURCE.replace("void main() {}", "void main() {\n  y = SCALE;\n}");
+        let template = ShaderTemplate::parse("broken.comp.glsl", &broken).unwrap();
+        substs.insert("SCALE", 2.0f32.into());
+        substs.insert("CLAMP", true.into());
+        let err = match template.compile(&substs, gl::COMPUTE_SHADER) {
+            Err(err) => err,
+            Ok(_) => panic!("expected a compile error"),
+        };
+
+        match &err {
+            ShaderError::Compile { diagnostics, .. } => {
+                assert_eq!(diagnostics[0].severity, Severity::Error);
+                assert_eq!(diagnostics[0].line, Some(10));
+            }
+            other => panic!("expected a compile error, got {:?}", other),
+        }
+        // The snippet is the line from the template, not the instantiated one
+        assert!(err.to_string().contains("--> broken.comp.glsl:10:"));
+        assert!(err.to_string().contains("10 |   y = SCALE;\n"));
+    }
+}